@@ -0,0 +1,695 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tokio::time::Duration;
+
+/// Default time between polls when streaming
+pub(crate) const POLL_INTERVAL_MS: u64 = 120000;
+
+// The social network neutral view of a post. Every backend maps its own
+// API types into these so the renderers in `ui` never see egg-mode types.
+
+#[derive(Debug, Clone)]
+pub(crate) struct Post {
+    pub(crate) id: String,
+    pub(crate) author: Author,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) text: String,
+    pub(crate) urls: Vec<Link>,
+    pub(crate) media: Vec<Media>,
+    /// Mentions, hashtags and cashtags in the text
+    pub(crate) tags: Vec<Tag>,
+    pub(crate) repost_count: u64,
+    pub(crate) like_count: u64,
+    /// Name of the app the post was made from
+    pub(crate) source: Option<String>,
+    /// Full name of the place the post is tagged with
+    pub(crate) place: Option<String>,
+    pub(crate) reply_to: Option<ReplyTo>,
+    /// Set when this post is a repost (retweet, boost) of another post
+    pub(crate) reposted: Option<Box<Post>>,
+    /// Set when this post quotes another post
+    pub(crate) quoted: Option<Box<Post>>,
+    /// Why the post is in the `me` feed
+    pub(crate) activity: Option<Activity>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Activity {
+    Authored,
+    Liked,
+}
+
+impl Activity {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Activity::Authored => "authored",
+            Activity::Liked => "liked",
+        }
+    }
+}
+
+fn mark(posts: Vec<Post>, activity: Activity) -> Vec<Post> {
+    posts
+        .into_iter()
+        .map(|post| Post {
+            activity: Some(activity),
+            ..post
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Author {
+    pub(crate) handle: String,
+    pub(crate) name: String,
+    /// Link to the author's profile
+    pub(crate) url: String,
+}
+
+impl Default for Author {
+    fn default() -> Author {
+        Author {
+            handle: "".to_string(),
+            name: "<unknown>".to_string(),
+            url: "".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ReplyTo {
+    pub(crate) handle: String,
+    /// Link to the post being replied to
+    pub(crate) url: String,
+}
+
+/// A link as it appears in the post text along with where it really goes
+#[derive(Debug, Clone)]
+pub(crate) struct Link {
+    pub(crate) url: String,
+    pub(crate) display_url: String,
+    pub(crate) expanded_url: Option<String>,
+    /// Byte range of `url` in the post text, when the network says
+    pub(crate) range: Option<(usize, usize)>,
+}
+
+/// An attached image (or video thumbnail) and the link to it in the post text
+#[derive(Debug, Clone)]
+pub(crate) struct Media {
+    pub(crate) url: String,
+    pub(crate) display_url: String,
+    pub(crate) media_url: String,
+    /// Byte range of `url` in the post text, when the network says
+    pub(crate) range: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Tag {
+    pub(crate) kind: TagKind,
+    /// Byte range in the post text, including the leading @, # or $
+    pub(crate) range: (usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TagKind {
+    Mention,
+    Hashtag,
+    /// Cashtags like $TWTR
+    Symbol,
+}
+
+/// Appends a link to the post text on its own word, returning its range
+pub(crate) fn append_link(text: &mut String, url: &str) -> (usize, usize) {
+    if !text.is_empty() {
+        text.push(' ');
+    }
+    let start = text.len();
+    text.push_str(url);
+    (start, text.len())
+}
+
+/// Moves entity ranges along for text that's been put after something else
+pub(crate) fn shift_entities(links: &mut [Link], tags: &mut [Tag], by: usize) {
+    for link in links.iter_mut() {
+        link.range = link.range.map(|(start, end)| (start + by, end + by));
+    }
+    for tag in tags.iter_mut() {
+        tag.range = (tag.range.0 + by, tag.range.1 + by);
+    }
+}
+
+/// Checks an entity range against the text it should cover, searching for
+/// the text instead when the range is off. Networks count offsets in
+/// different ways and don't always agree with their own text.
+pub(crate) fn locate(text: &str, range: (usize, usize), needle: &str) -> Option<(usize, usize)> {
+    match text.get(range.0..range.1) {
+        Some(found) if found.eq_ignore_ascii_case(needle) => Some(range),
+        _ => text
+            .to_ascii_lowercase()
+            .find(&needle.to_ascii_lowercase())
+            .map(|start| (start, start + needle.len())),
+    }
+}
+
+/// The social networks babble knows how to read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BackendKind {
+    #[default]
+    Twitter,
+    Mastodon,
+    Bluesky,
+    Rss,
+}
+
+impl BackendKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Twitter => "twitter",
+            BackendKind::Mastodon => "mastodon",
+            BackendKind::Bluesky => "bluesky",
+            BackendKind::Rss => "rss",
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<BackendKind> {
+        match s.to_lowercase().as_str() {
+            "twitter" => Ok(BackendKind::Twitter),
+            "mastodon" => Ok(BackendKind::Mastodon),
+            "bluesky" => Ok(BackendKind::Bluesky),
+            "rss" => Ok(BackendKind::Rss),
+            _ => Err(anyhow::anyhow!("Unknown backend {}", s)),
+        }
+    }
+}
+
+/// Which timeline to read, mirroring the CLI subcommands
+#[derive(Debug, Clone)]
+pub(crate) enum Feed {
+    Home,
+    List(String),
+    Me,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Query {
+    /// Maximum number of posts to return, unless `after` asks for more
+    pub(crate) limit: usize,
+    /// Only return posts newer than the post with this id
+    pub(crate) since: Option<String>,
+    /// When that post was made, for backends that can lose track of it
+    pub(crate) since_time: Option<DateTime<Utc>>,
+    /// In the me feed, only return likes newer than the like of the post
+    /// with this id
+    pub(crate) liked_since: Option<String>,
+    /// Only return posts made at or after this time, paging back as far as
+    /// it takes to get to it
+    pub(crate) after: Option<DateTime<Utc>>,
+    /// Only return posts made before this time
+    pub(crate) before: Option<DateTime<Utc>>,
+}
+
+/// Most pages to fetch when paging back for a date range
+pub(crate) const MAX_PAGES: usize = 20;
+
+impl Query {
+    /// How many posts to ask for per page, given the most a backend
+    /// returns at once
+    pub(crate) fn page_size(&self, max: usize) -> usize {
+        if self.paged(max) {
+            max
+        } else {
+            self.limit
+        }
+    }
+
+    // Whether this query may need more than one page of `max` posts
+    fn paged(&self, max: usize) -> bool {
+        self.after.is_some() || self.before.is_some() || self.limit > max
+    }
+
+    /// Given the posts so far, newest first, how many the last page had and
+    /// the most a page can have, whether to fetch the next page back
+    pub(crate) fn wants_more(&self, posts: &[Post], page_len: usize, max: usize) -> bool {
+        if page_len == 0 || !self.paged(max) {
+            return false;
+        }
+        // Paged back to the newest post we already had
+        if let Some(ref since) = self.since {
+            if posts.iter().any(|p| &p.id == since) {
+                return false;
+            }
+        }
+        match self.after {
+            Some(after) => posts.last().is_some_and(|p| p.created_at > after),
+            None => posts.iter().filter(|p| self.in_range(p)).count() < self.limit,
+        }
+    }
+
+    fn in_range(&self, post: &Post) -> bool {
+        self.after.is_none_or(|after| post.created_at >= after)
+            && self.before.is_none_or(|before| post.created_at < before)
+    }
+
+    /// The posts in the date range, and no more than `limit` of them unless
+    /// the range has a start
+    pub(crate) fn keep(&self, posts: Vec<Post>) -> Vec<Post> {
+        let mut posts: Vec<Post> = posts.into_iter().filter(|p| self.in_range(p)).collect();
+        if self.after.is_none() {
+            posts.truncate(self.limit);
+        }
+        posts
+    }
+}
+
+#[async_trait]
+pub(crate) trait Backend: Send + Sync {
+    /// Handle of the signed in account
+    fn handle(&self) -> &str;
+
+    /// How long to wait between polls when streaming
+    fn poll_interval(&self) -> Duration {
+        Duration::from_millis(POLL_INTERVAL_MS)
+    }
+
+    /// What's left of the rate limit as of the last request, if the API
+    /// says
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
+
+    /// Posts from the accounts the user follows, newest first
+    async fn home(&self, query: &Query) -> Result<Vec<Post>>;
+
+    /// Posts from the named list, newest first
+    async fn list(&self, name: &str, query: &Query) -> Result<Vec<Post>>;
+
+    /// The user's own posts, newest first
+    async fn me(&self, query: &Query) -> Result<Vec<Post>>;
+
+    /// Posts the user has liked, newest first
+    async fn likes(&self, _query: &Query) -> Result<Vec<Post>> {
+        Ok(vec![])
+    }
+
+    async fn fetch(&self, feed: &Feed, query: &Query) -> Result<Fetched> {
+        let posts = match feed {
+            Feed::Home => self.home(query).await?,
+            Feed::List(name) => self.list(name, query).await?,
+            Feed::Me => {
+                // Likes come in the order they were made, which has nothing
+                // to do with when the posts were, so they keep their own place
+                let likes_query = Query {
+                    since: None,
+                    ..query.clone()
+                };
+                let likes = take_newer(self.likes(&likes_query).await?, &query.liked_since);
+                let newest_like = likes.first().map(|p| p.id.clone());
+                let mut posts = mark(self.me(query).await?, Activity::Authored);
+                for liked in mark(likes, Activity::Liked) {
+                    // Liking your own post leaves it authored
+                    if !posts.iter().any(|p| p.id == liked.id) {
+                        posts.push(liked);
+                    }
+                }
+                posts.sort_by_key(|p| std::cmp::Reverse(p.created_at));
+                return Ok(Fetched {
+                    posts: query.keep(posts),
+                    newest_like,
+                });
+            }
+        };
+        Ok(Fetched {
+            posts,
+            newest_like: None,
+        })
+    }
+}
+
+/// What a fetch brought back
+pub(crate) struct Fetched {
+    /// Newest first
+    pub(crate) posts: Vec<Post>,
+    /// In the me feed, the id of the newest like, for `liked_since`
+    pub(crate) newest_like: Option<String>,
+}
+
+/// For APIs that can't filter by id themselves: keeps the posts (newest
+/// first) that come before the one with the `since` id.
+pub(crate) fn take_newer(posts: Vec<Post>, since: &Option<String>) -> Vec<Post> {
+    match since {
+        Some(since) => posts.into_iter().take_while(|p| &p.id != since).collect(),
+        None => posts,
+    }
+}
+
+/// Requests left in the current rate limit window and when it resets
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RateLimit {
+    pub(crate) remaining: u32,
+    pub(crate) reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    /// From a unix timestamp, the way Twitter and Bluesky give it
+    pub(crate) fn new(remaining: i64, reset: i64) -> Option<RateLimit> {
+        Some(RateLimit {
+            remaining: remaining.max(0) as u32,
+            reset: Utc.timestamp_opt(reset, 0).single()?,
+        })
+    }
+}
+
+/// The error for a 429 from an API, with when it says to try again
+#[derive(Debug)]
+pub(crate) struct RateLimited {
+    pub(crate) reset: Option<DateTime<Utc>>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limited")
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Keeps the rate limit headers of a response and turns a 429 into
+/// `RateLimited`. Mastodon sends X-RateLimit-Reset as a timestamp and
+/// Bluesky sends RateLimit-Reset as unix seconds.
+pub(crate) fn check_response(
+    response: reqwest::Response,
+    rate_limit: &Mutex<Option<RateLimit>>,
+) -> Result<reqwest::Response> {
+    let header = |name: &str| -> Option<&str> {
+        [
+            format!("x-ratelimit-{}", name),
+            format!("ratelimit-{}", name),
+        ]
+        .iter()
+        .find_map(|name| response.headers().get(name.as_str()))
+        .and_then(|value| value.to_str().ok())
+    };
+    let reset = header("reset").and_then(|reset| match reset.parse::<i64>() {
+        Ok(secs) => Utc.timestamp_opt(secs, 0).single(),
+        Err(_) => DateTime::parse_from_rfc3339(reset)
+            .ok()
+            .map(|at| at.with_timezone(&Utc)),
+    });
+    let remaining = header("remaining").and_then(|n| n.parse::<u32>().ok());
+    if let (Some(remaining), Some(reset)) = (remaining, reset) {
+        *rate_limit.lock().unwrap() = Some(RateLimit { remaining, reset });
+    }
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|secs| secs.parse::<i64>().ok())
+            .map(|secs| Utc::now() + chrono::Duration::seconds(secs));
+        return Err(RateLimited {
+            reset: retry_after.or(reset),
+        }
+        .into());
+    }
+    Ok(response)
+}
+
+/// What to do about a failed fetch while streaming
+pub(crate) enum Retry {
+    /// Wait until the rate limit resets
+    At(DateTime<Utc>),
+    /// A network blip or server error, so try again after a while
+    Backoff,
+    /// Something retrying won't fix, like bad credentials or a missing list
+    Never,
+}
+
+pub(crate) fn retry(err: &anyhow::Error) -> Retry {
+    if let Some(limited) = err.downcast_ref::<RateLimited>() {
+        return limited.reset.map_or(Retry::Backoff, Retry::At);
+    }
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        // A reply that doesn't parse will be the same next time
+        if err.is_decode() {
+            return Retry::Never;
+        }
+        return match err.status() {
+            Some(status) if retry_status(status.as_u16()) => Retry::Backoff,
+            Some(_) => Retry::Never,
+            None if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() => {
+                Retry::Backoff
+            }
+            None => Retry::Never,
+        };
+    }
+    if let Some(err) = err.downcast_ref::<egg_mode::error::Error>() {
+        use egg_mode::error::Error;
+        return match err {
+            Error::RateLimit(reset) => Utc
+                .timestamp_opt(*reset as i64, 0)
+                .single()
+                .map_or(Retry::Backoff, Retry::At),
+            Error::NetError(err)
+                if err.is_connect()
+                    || err.is_timeout()
+                    || err.is_closed()
+                    || err.is_incomplete_message() =>
+            {
+                Retry::Backoff
+            }
+            Error::IOError(err) if transient(err) => Retry::Backoff,
+            Error::BadStatus(status) if retry_status(status.as_u16()) => Retry::Backoff,
+            // Over capacity and internal error
+            Error::TwitterError(_, errors)
+                if errors.errors.iter().any(|e| e.code == 130 || e.code == 131) =>
+            {
+                Retry::Backoff
+            }
+            _ => Retry::Never,
+        };
+    }
+    match err.downcast_ref::<std::io::Error>() {
+        Some(err) if transient(err) => Retry::Backoff,
+        _ => Retry::Never,
+    }
+}
+
+// Server errors, and too many requests without saying when to come back
+fn retry_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+// Dropped connections rather than missing files or bad data
+fn transient(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | TimedOut
+            | Interrupted
+            | UnexpectedEof
+    )
+}
+
+/// Path to a file in the user's home directory, used for saved logins
+pub(crate) fn home_file(name: &str) -> PathBuf {
+    let mut path = std::env::home_dir().unwrap();
+    path.push(name);
+    path
+}
+
+/// A bare post for tests to fill in
+#[cfg(test)]
+pub(crate) fn test_post(id: &str, created_at: DateTime<Utc>) -> Post {
+    Post {
+        id: id.to_string(),
+        author: Author::default(),
+        created_at,
+        text: format!("Post {}", id),
+        urls: vec![],
+        media: vec![],
+        tags: vec![],
+        repost_count: 0,
+        like_count: 0,
+        source: None,
+        place: None,
+        reply_to: None,
+        reposted: None,
+        quoted: None,
+        activity: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+
+    async fn checked(
+        status: usize,
+        headers: &[(&str, &str)],
+    ) -> (Result<reqwest::Response>, Option<RateLimit>) {
+        let mut server = Server::new_async().await;
+        let mut mock = server.mock("GET", "/").with_status(status);
+        for &(name, value) in headers {
+            mock = mock.with_header(name, value);
+        }
+        mock.create_async().await;
+        let response = reqwest::get(server.url()).await.unwrap();
+        let rate_limit = Mutex::new(None);
+        let checked = check_response(response, &rate_limit);
+        let limit = *rate_limit.lock().unwrap();
+        (checked, limit)
+    }
+
+    #[tokio::test]
+    async fn reads_mastodon_rate_limit_headers() {
+        let (response, limit) = checked(
+            200,
+            &[
+                ("x-ratelimit-remaining", "12"),
+                ("x-ratelimit-reset", "2021-10-15T12:05:00.000Z"),
+            ],
+        )
+        .await;
+        assert!(response.is_ok());
+        let limit = limit.unwrap();
+        assert_eq!(limit.remaining, 12);
+        assert_eq!(limit.reset, Utc.timestamp_opt(1_634_299_500, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn reads_bluesky_rate_limit_headers() {
+        let (_, limit) = checked(
+            200,
+            &[
+                ("ratelimit-remaining", "2999"),
+                ("ratelimit-reset", "1634299500"),
+            ],
+        )
+        .await;
+        let limit = limit.unwrap();
+        assert_eq!(limit.remaining, 2999);
+        assert_eq!(limit.reset, Utc.timestamp_opt(1_634_299_500, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn ignores_partial_rate_limit_headers() {
+        let (_, limit) = checked(200, &[("x-ratelimit-remaining", "12")]).await;
+        assert!(limit.is_none());
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_waits_for_retry_after() {
+        let (response, _) = checked(
+            429,
+            &[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "2021-10-15T12:05:00.000Z"),
+                ("retry-after", "30"),
+            ],
+        )
+        .await;
+        let err = response.unwrap_err();
+        let reset = err.downcast_ref::<RateLimited>().unwrap().reset.unwrap();
+        let wait = (reset - Utc::now()).num_seconds();
+        assert!((28..=30).contains(&wait), "waiting {}s", wait);
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_falls_back_to_the_reset() {
+        let (response, _) = checked(
+            429,
+            &[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "2021-10-15T12:05:00.000Z"),
+            ],
+        )
+        .await;
+        let err = response.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RateLimited>().unwrap().reset,
+            Some(Utc.timestamp_opt(1_634_299_500, 0).unwrap())
+        );
+    }
+
+    async fn failed<T: serde::de::DeserializeOwned>(status: usize, body: &str) -> anyhow::Error {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_status(status)
+            .with_body(body)
+            .create_async()
+            .await;
+        let result: Result<T> = async {
+            Ok(reqwest::get(server.url())
+                .await?
+                .error_for_status()?
+                .json()
+                .await?)
+        }
+        .await;
+        result.err().unwrap()
+    }
+
+    #[tokio::test]
+    async fn bad_replies_are_never_retried() {
+        assert!(matches!(
+            retry(&failed::<Vec<u32>>(200, "<html>").await),
+            Retry::Never
+        ));
+        assert!(matches!(
+            retry(&failed::<Vec<u32>>(200, "[\"a\"]").await),
+            Retry::Never
+        ));
+        let err = serde_json::from_str::<Vec<u32>>("{").unwrap_err();
+        assert!(matches!(retry(&err.into()), Retry::Never));
+    }
+
+    #[tokio::test]
+    async fn server_errors_and_too_many_requests_back_off() {
+        for status in [429, 500, 503] {
+            assert!(matches!(
+                retry(&failed::<()>(status, "").await),
+                Retry::Backoff
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn other_client_errors_are_never_retried() {
+        for status in [400, 401, 403, 404] {
+            assert!(matches!(
+                retry(&failed::<()>(status, "").await),
+                Retry::Never
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_errors_back_off() {
+        // Nothing listens on the discard port
+        let err = reqwest::get("http://127.0.0.1:9").await.unwrap_err();
+        assert!(matches!(retry(&err.into()), Retry::Backoff));
+
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(matches!(retry(&reset.into()), Retry::Backoff));
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(matches!(retry(&missing.into()), Retry::Never));
+    }
+}
@@ -0,0 +1,808 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::Write;
+use std::sync::Mutex;
+
+use crate::{
+    backend::{
+        append_link, check_response, home_file, take_newer, Author, Backend, Link, Media, Post,
+        Query, RateLimit, ReplyTo, Tag, TagKind, MAX_PAGES,
+    },
+    MyConfig,
+};
+
+const SESSION_FILE: &str = ".bluesky_cli_session";
+const DEFAULT_PDS: &str = "https://bsky.social";
+// The AppView refuses to return more than this many posts per call
+const MAX_PAGE_SIZE: usize = 100;
+
+pub(crate) struct Bluesky {
+    client: reqwest::Client,
+    pds: String,
+    // Access tokens expire after a couple of hours, so streaming will swap
+    // in a refreshed session as it goes
+    session: Mutex<Session>,
+    handle: String,
+    feeds: std::collections::HashMap<String, String>,
+    rate_limit: Mutex<Option<RateLimit>>,
+}
+
+impl Bluesky {
+    pub(crate) async fn init(cfg: &MyConfig) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let pds = match cfg.bluesky_pds.trim().trim_end_matches('/') {
+            "" => DEFAULT_PDS.to_string(),
+            pds => pds.to_string(),
+        };
+
+        let session = match fetch_login(&client, &pds).await {
+            Ok(session) => session,
+            Err(_) => login(&client, &pds).await?,
+        };
+
+        Ok(Bluesky {
+            client,
+            pds,
+            handle: session.handle.clone(),
+            session: Mutex::new(session),
+            feeds: cfg
+                .bluesky_feeds
+                .iter()
+                .map(|(name, uri)| (name.to_lowercase(), uri.clone()))
+                .collect(),
+            rate_limit: Mutex::new(None),
+        })
+    }
+
+    async fn get<T>(&self, method: &str, params: &[(&str, String)]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let mut response = self.send(method, params).await?;
+        if response.status() == StatusCode::BAD_REQUEST
+            || response.status() == StatusCode::UNAUTHORIZED
+        {
+            // Only an expired access token is fixed by refreshing, anything
+            // else is a request that will never work
+            let status = response.status();
+            let error: XrpcError = response.json().await.unwrap_or_default();
+            if error.error != "ExpiredToken" {
+                return Err(match error.error.as_str() {
+                    "" => anyhow!("Bluesky returned {}", status),
+                    _ => anyhow!("{}: {} ({})", error.error, error.message, status),
+                });
+            }
+            let refresh_jwt = self.session.lock().unwrap().refresh_jwt.clone();
+            let session = refresh_session(&self.client, &self.pds, &refresh_jwt).await?;
+            save_login(&self.pds, &session).await?;
+            *self.session.lock().unwrap() = session;
+            response = self.send(method, params).await?;
+        }
+        Ok(check_response(response, &self.rate_limit)?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn send(&self, method: &str, params: &[(&str, String)]) -> Result<reqwest::Response> {
+        let access_jwt = self.session.lock().unwrap().access_jwt.clone();
+        Ok(self
+            .client
+            .get(format!("{}/xrpc/{}", self.pds, method))
+            .bearer_auth(access_jwt)
+            .query(params)
+            .send()
+            .await?)
+    }
+
+    async fn fetch_feed(
+        &self,
+        method: &str,
+        mut params: Vec<(&str, String)>,
+        query: &Query,
+    ) -> Result<Vec<Post>> {
+        let page_size = query.page_size(MAX_PAGE_SIZE);
+        params.push(("limit", page_size.to_string()));
+        let mut posts: Vec<Post> = vec![];
+        for _ in 0..MAX_PAGES {
+            let page: FeedPage = self.get(method, &params).await?;
+            posts.extend(page.feed.iter().map(to_post));
+            let cursor = match page.cursor {
+                Some(cursor) if query.wants_more(&posts, page.feed.len(), MAX_PAGE_SIZE) => cursor,
+                _ => break,
+            };
+            params.retain(|(name, _)| *name != "cursor");
+            params.push(("cursor", cursor));
+        }
+        // There's no since parameter in the feed APIs so filter here
+        Ok(query.keep(take_newer(posts, &query.since)))
+    }
+}
+
+#[async_trait]
+impl Backend for Bluesky {
+    fn handle(&self) -> &str {
+        &self.handle
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    async fn me(&self, query: &Query) -> Result<Vec<Post>> {
+        let did = self.session.lock().unwrap().did.clone();
+        self.fetch_feed("app.bsky.feed.getAuthorFeed", vec![("actor", did)], query)
+            .await
+    }
+
+    async fn likes(&self, query: &Query) -> Result<Vec<Post>> {
+        let did = self.session.lock().unwrap().did.clone();
+        self.fetch_feed("app.bsky.feed.getActorLikes", vec![("actor", did)], query)
+            .await
+    }
+
+    // Lists are custom feeds, either named in the config or given directly
+    // by their at:// URI
+    async fn list(&self, feed_name: &str, query: &Query) -> Result<Vec<Post>> {
+        let uri = if feed_name.starts_with("at://") {
+            feed_name.to_string()
+        } else {
+            self.feeds
+                .get(&feed_name.to_lowercase())
+                .cloned()
+                .ok_or(anyhow!("Feed not found"))?
+        };
+        if uri.contains("/app.bsky.graph.list/") {
+            self.fetch_feed("app.bsky.feed.getListFeed", vec![("list", uri)], query)
+                .await
+        } else {
+            self.fetch_feed("app.bsky.feed.getFeed", vec![("feed", uri)], query)
+                .await
+        }
+    }
+
+    async fn home(&self, query: &Query) -> Result<Vec<Post>> {
+        self.fetch_feed("app.bsky.feed.getTimeline", vec![], query)
+            .await
+    }
+}
+
+fn to_post(item: &FeedViewPost) -> Post {
+    let mut post = post_from_record(
+        &item.post.uri,
+        &item.post.author,
+        &item.post.record,
+        item.post.embed.as_ref(),
+        &item.post.indexed_at,
+    );
+    post.repost_count = item.post.repost_count;
+    post.like_count = item.post.like_count;
+    post.reply_to = item.post.record.reply.as_ref().map(|reply| ReplyTo {
+        handle: item
+            .reply
+            .as_ref()
+            .and_then(|r| r.parent.author.as_ref())
+            .map(|a| a.handle.clone())
+            .unwrap_or_else(|| uri_authority(&reply.parent.uri).to_string()),
+        url: web_url(&reply.parent.uri),
+    });
+
+    match item.reason {
+        Some(Reason::Repost {
+            ref by,
+            ref indexed_at,
+        }) => Post {
+            id: format!("{}#repost-{}", &item.post.uri, &by.did),
+            author: to_author(by),
+            created_at: *indexed_at,
+            text: "".to_string(),
+            urls: vec![],
+            media: vec![],
+            tags: vec![],
+            repost_count: post.repost_count,
+            like_count: post.like_count,
+            source: None,
+            place: None,
+            reply_to: None,
+            reposted: Some(Box::new(post)),
+            quoted: None,
+            activity: None,
+        },
+        _ => post,
+    }
+}
+
+fn post_from_record(
+    uri: &str,
+    author: &Profile,
+    record: &Record,
+    embed: Option<&Embed>,
+    indexed_at: &DateTime<Utc>,
+) -> Post {
+    let mut text = record.text.clone();
+    let mut urls: Vec<Link> = vec![];
+    let mut tags: Vec<Tag> = vec![];
+    for facet in &record.facets {
+        let range = (facet.index.byte_start, facet.index.byte_end);
+        let display = match record.text.get(range.0..range.1) {
+            Some(display) => display.to_string(),
+            None => continue,
+        };
+        for feature in &facet.features {
+            match feature {
+                Feature::Link { uri } => urls.push(Link {
+                    url: display.clone(),
+                    display_url: display.clone(),
+                    expanded_url: Some(uri.clone()),
+                    range: Some(range),
+                }),
+                Feature::Mention {} => tags.push(Tag {
+                    kind: TagKind::Mention,
+                    range,
+                }),
+                Feature::Tag {} => tags.push(Tag {
+                    kind: TagKind::Hashtag,
+                    range,
+                }),
+                Feature::Other => (),
+            }
+        }
+    }
+    let mut media: Vec<Media> = vec![];
+    let mut quoted: Option<Box<Post>> = None;
+
+    // Embeds aren't part of the text, so tack their links onto the end
+    // where the renderers will pick them up
+    let mut add_embed = |embed: &Embed| match embed {
+        Embed::Images { images } => {
+            for image in images {
+                media.push(Media {
+                    url: image.fullsize.clone(),
+                    display_url: image.fullsize.clone(),
+                    media_url: image.fullsize.clone(),
+                    range: Some(append_link(&mut text, &image.fullsize)),
+                });
+            }
+        }
+        Embed::Video {
+            thumbnail,
+            playlist,
+        } => {
+            media.push(Media {
+                url: playlist.clone(),
+                display_url: playlist.clone(),
+                media_url: thumbnail.clone().unwrap_or_else(|| playlist.clone()),
+                range: Some(append_link(&mut text, playlist)),
+            });
+        }
+        Embed::External { external }
+            if !urls
+                .iter()
+                .any(|u| u.expanded_url.as_ref() == Some(&external.uri)) =>
+        {
+            urls.push(Link {
+                url: external.uri.clone(),
+                display_url: external.uri.clone(),
+                expanded_url: Some(external.uri.clone()),
+                range: Some(append_link(&mut text, &external.uri)),
+            });
+        }
+        _ => (),
+    };
+    match embed {
+        Some(Embed::Record {
+            record: EmbeddedRecord::Post(ref qt),
+        }) => quoted = Some(Box::new(quoted_post(qt))),
+        Some(Embed::RecordWithMedia {
+            record: RecordWithMediaRecord { ref record },
+            ref media,
+        }) => {
+            add_embed(media);
+            if let EmbeddedRecord::Post(ref qt) = record {
+                quoted = Some(Box::new(quoted_post(qt)));
+            }
+        }
+        Some(embed) => add_embed(embed),
+        None => (),
+    }
+
+    Post {
+        id: uri.to_string(),
+        author: to_author(author),
+        created_at: record
+            .created_at
+            .parse::<DateTime<Utc>>()
+            .unwrap_or(*indexed_at),
+        text,
+        urls,
+        media,
+        tags,
+        repost_count: 0,
+        like_count: 0,
+        source: None,
+        place: None,
+        reply_to: None,
+        reposted: None,
+        quoted,
+        activity: None,
+    }
+}
+
+fn quoted_post(qt: &ViewRecord) -> Post {
+    let mut post = post_from_record(
+        &qt.uri,
+        &qt.author,
+        &qt.value,
+        qt.embeds.first(),
+        &qt.indexed_at,
+    );
+    post.repost_count = qt.repost_count;
+    post.like_count = qt.like_count;
+    post
+}
+
+fn to_author(profile: &Profile) -> Author {
+    Author {
+        handle: profile.handle.clone(),
+        name: profile
+            .display_name
+            .clone()
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| profile.handle.clone()),
+        url: format!("https://bsky.app/profile/{}", &profile.handle),
+    }
+}
+
+// at://<did>/app.bsky.feed.post/<rkey>
+fn uri_authority(uri: &str) -> &str {
+    uri.trim_start_matches("at://")
+        .split('/')
+        .next()
+        .unwrap_or("")
+}
+
+fn web_url(uri: &str) -> String {
+    format!(
+        "https://bsky.app/profile/{}/post/{}",
+        uri_authority(uri),
+        uri.rsplit('/').next().unwrap_or("")
+    )
+}
+
+// The subset of the app.bsky lexicons that babble renders
+
+#[derive(Debug, Deserialize)]
+struct FeedPage {
+    feed: Vec<FeedViewPost>,
+    /// Where the next page back starts, missing at the end of the feed
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedViewPost {
+    post: PostView,
+    reply: Option<ReplyView>,
+    reason: Option<Reason>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostView {
+    uri: String,
+    author: Profile,
+    record: Record,
+    embed: Option<Embed>,
+    #[serde(default)]
+    repost_count: u64,
+    #[serde(default)]
+    like_count: u64,
+    indexed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    did: String,
+    handle: String,
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    #[serde(default)]
+    text: String,
+    // Client supplied, so not always a valid timestamp
+    #[serde(default)]
+    created_at: String,
+    #[serde(default)]
+    facets: Vec<Facet>,
+    reply: Option<ReplyRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Facet {
+    index: ByteSlice,
+    features: Vec<Feature>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ByteSlice {
+    byte_start: usize,
+    byte_end: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum Feature {
+    #[serde(rename = "app.bsky.richtext.facet#link")]
+    Link { uri: String },
+    #[serde(rename = "app.bsky.richtext.facet#mention")]
+    Mention {},
+    #[serde(rename = "app.bsky.richtext.facet#tag")]
+    Tag {},
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplyRef {
+    parent: StrongRef,
+}
+
+#[derive(Debug, Deserialize)]
+struct StrongRef {
+    uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplyView {
+    parent: ReplyParent,
+}
+
+// Can also be a not found or blocked post, which have no author
+#[derive(Debug, Deserialize)]
+struct ReplyParent {
+    author: Option<Profile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum Reason {
+    #[serde(rename = "app.bsky.feed.defs#reasonRepost")]
+    Repost {
+        by: Profile,
+        #[serde(rename = "indexedAt")]
+        indexed_at: DateTime<Utc>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum Embed {
+    #[serde(rename = "app.bsky.embed.images#view")]
+    Images { images: Vec<ImageView> },
+    #[serde(rename = "app.bsky.embed.video#view")]
+    Video {
+        thumbnail: Option<String>,
+        playlist: String,
+    },
+    #[serde(rename = "app.bsky.embed.external#view")]
+    External { external: ExternalView },
+    #[serde(rename = "app.bsky.embed.record#view")]
+    Record { record: EmbeddedRecord },
+    #[serde(rename = "app.bsky.embed.recordWithMedia#view")]
+    RecordWithMedia {
+        record: RecordWithMediaRecord,
+        media: Box<Embed>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageView {
+    fullsize: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExternalView {
+    uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordWithMediaRecord {
+    record: EmbeddedRecord,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum EmbeddedRecord {
+    #[serde(rename = "app.bsky.embed.record#viewRecord")]
+    Post(Box<ViewRecord>),
+    // Feeds, lists, deleted and blocked posts
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ViewRecord {
+    uri: String,
+    author: Profile,
+    value: Record,
+    #[serde(default)]
+    embeds: Vec<Embed>,
+    #[serde(default)]
+    repost_count: u64,
+    #[serde(default)]
+    like_count: u64,
+    indexed_at: DateTime<Utc>,
+}
+
+// The body of an XRPC error response
+#[derive(Debug, Default, Deserialize)]
+struct XrpcError {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    access_jwt: String,
+    refresh_jwt: String,
+    did: String,
+    handle: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedLogin {
+    pds: String,
+    session: Session,
+}
+
+async fn refresh_session(
+    client: &reqwest::Client,
+    pds: &str,
+    refresh_jwt: &str,
+) -> Result<Session> {
+    Ok(client
+        .post(format!("{}/xrpc/com.atproto.server.refreshSession", pds))
+        .bearer_auth(refresh_jwt)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn fetch_login(client: &reqwest::Client, pds: &str) -> Result<Session> {
+    let file = std::fs::File::open(home_file(SESSION_FILE))?;
+    let reader = std::io::BufReader::new(file);
+    let saved: SavedLogin = serde_json::from_reader(reader)?;
+    if saved.pds != pds {
+        return Err(anyhow!("Saved login is for {}", &saved.pds));
+    }
+    // Refreshing both checks the session is still good and gives us a fresh
+    // access token to start with
+    let session = refresh_session(client, pds, &saved.session.refresh_jwt).await?;
+    save_login(pds, &session).await?;
+    Ok(session)
+}
+
+async fn save_login(pds: &str, session: &Session) -> Result<()> {
+    use std::fs::OpenOptions;
+    let file = &OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(home_file(SESSION_FILE))?;
+    serde_json::to_writer(
+        file,
+        &SavedLogin {
+            pds: pds.to_string(),
+            session: session.clone(),
+        },
+    )?;
+    Ok(())
+}
+
+// Sign in with an app password (Settings → Privacy and security → App
+// passwords) rather than the account password
+async fn login(client: &reqwest::Client, pds: &str) -> Result<Session> {
+    let mut stde = std::io::stderr();
+    eprint!("Enter your Bluesky handle: ");
+    let _ = stde.flush();
+    let mut identifier = String::new();
+    std::io::stdin().read_line(&mut identifier)?;
+    eprint!("Enter a Bluesky app password: ");
+    let _ = stde.flush();
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+
+    let session: Session = client
+        .post(format!("{}/xrpc/com.atproto.server.createSession", pds))
+        .json(&serde_json::json!({
+            "identifier": identifier.trim(),
+            "password": password.trim(),
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    save_login(pds, &session).await?;
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Mock, Server, ServerGuard};
+
+    fn bluesky(server: &ServerGuard) -> Bluesky {
+        Bluesky {
+            client: reqwest::Client::new(),
+            pds: server.url(),
+            session: Mutex::new(Session {
+                access_jwt: "access".to_string(),
+                refresh_jwt: "refresh".to_string(),
+                did: "did:plc:alice".to_string(),
+                handle: "alice.bsky.social".to_string(),
+            }),
+            handle: "alice.bsky.social".to_string(),
+            feeds: std::collections::HashMap::new(),
+            rate_limit: Mutex::new(None),
+        }
+    }
+
+    async fn timeline_error(server: &mut ServerGuard, error: &str) {
+        server
+            .mock("GET", "/xrpc/app.bsky.feed.getTimeline")
+            .match_query(mockito::Matcher::Any)
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(format!(r#"{{"error": "{}", "message": "Nope"}}"#, error))
+            .create_async()
+            .await;
+    }
+
+    // Refreshing fails so the test never gets as far as saving a session
+    async fn refresh(server: &mut ServerGuard, hits: usize) -> Mock {
+        server
+            .mock("POST", "/xrpc/com.atproto.server.refreshSession")
+            .match_header("authorization", "Bearer refresh")
+            .with_status(401)
+            .expect(hits)
+            .create_async()
+            .await
+    }
+
+    fn query() -> Query {
+        Query {
+            limit: 10,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshes_an_expired_token() {
+        let mut server = Server::new_async().await;
+        timeline_error(&mut server, "ExpiredToken").await;
+        let refresh = refresh(&mut server, 1).await;
+
+        assert!(bluesky(&server).home(&query()).await.is_err());
+        refresh.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn reports_other_bad_requests() {
+        let mut server = Server::new_async().await;
+        timeline_error(&mut server, "InvalidRequest").await;
+        let refresh = refresh(&mut server, 0).await;
+
+        let err = bluesky(&server).home(&query()).await.unwrap_err();
+        assert_eq!(err.to_string(), "InvalidRequest: Nope (400 Bad Request)");
+        refresh.assert_async().await;
+    }
+
+    // A repost by bob of a post by carol that quotes dave, as getTimeline
+    // sends it
+    const REPOST_OF_QUOTE: &str = r##"{
+        "post": {
+            "uri": "at://did:plc:carol/app.bsky.feed.post/3kq1",
+            "author": {"did": "did:plc:carol", "handle": "carol.bsky.social", "displayName": "Carol"},
+            "record": {
+                "$type": "app.bsky.feed.post",
+                "text": "Agreed with @dave.bsky.social #rust",
+                "createdAt": "2024-03-01T10:00:00Z",
+                "facets": [
+                    {"index": {"byteStart": 12, "byteEnd": 29},
+                     "features": [{"$type": "app.bsky.richtext.facet#mention", "did": "did:plc:dave"}]},
+                    {"index": {"byteStart": 30, "byteEnd": 35},
+                     "features": [{"$type": "app.bsky.richtext.facet#tag", "tag": "rust"}]}
+                ]
+            },
+            "embed": {
+                "$type": "app.bsky.embed.record#view",
+                "record": {
+                    "$type": "app.bsky.embed.record#viewRecord",
+                    "uri": "at://did:plc:dave/app.bsky.feed.post/3kp9",
+                    "author": {"did": "did:plc:dave", "handle": "dave.bsky.social"},
+                    "value": {
+                        "$type": "app.bsky.feed.post",
+                        "text": "See example.com",
+                        "createdAt": "2024-02-29T09:00:00Z",
+                        "facets": [
+                            {"index": {"byteStart": 4, "byteEnd": 15},
+                             "features": [{"$type": "app.bsky.richtext.facet#link", "uri": "https://example.com/"}]}
+                        ]
+                    },
+                    "likeCount": 7,
+                    "indexedAt": "2024-02-29T09:00:01Z"
+                }
+            },
+            "repostCount": 2,
+            "likeCount": 5,
+            "indexedAt": "2024-03-01T10:00:01Z"
+        },
+        "reason": {
+            "$type": "app.bsky.feed.defs#reasonRepost",
+            "by": {"did": "did:plc:bob", "handle": "bob.bsky.social", "displayName": "Bob"},
+            "indexedAt": "2024-03-01T12:00:00Z"
+        }
+    }"##;
+
+    #[test]
+    fn maps_reposts_and_quotes() {
+        let item: FeedViewPost = serde_json::from_str(REPOST_OF_QUOTE).unwrap();
+        let post = to_post(&item);
+        assert_eq!(
+            post.id,
+            "at://did:plc:carol/app.bsky.feed.post/3kq1#repost-did:plc:bob"
+        );
+        assert_eq!(post.author.handle, "bob.bsky.social");
+        assert_eq!(post.created_at.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+
+        let reposted = post.reposted.as_deref().unwrap();
+        assert_eq!(reposted.author.name, "Carol");
+        assert_eq!(reposted.text, "Agreed with @dave.bsky.social #rust");
+        assert_eq!((reposted.repost_count, reposted.like_count), (2, 5));
+        let tags: Vec<(TagKind, &str)> = reposted
+            .tags
+            .iter()
+            .map(|tag| (tag.kind, &reposted.text[tag.range.0..tag.range.1]))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                (TagKind::Mention, "@dave.bsky.social"),
+                (TagKind::Hashtag, "#rust")
+            ]
+        );
+
+        let quoted = reposted.quoted.as_deref().unwrap();
+        assert_eq!(quoted.id, "at://did:plc:dave/app.bsky.feed.post/3kp9");
+        // No display name, so the handle stands in
+        assert_eq!(quoted.author.name, "dave.bsky.social");
+        assert_eq!(quoted.text, "See example.com");
+        assert_eq!(quoted.like_count, 7);
+        assert_eq!(quoted.urls.len(), 1);
+        assert_eq!(quoted.urls[0].range, Some((4, 15)));
+        assert_eq!(
+            quoted.urls[0].expanded_url.as_deref(),
+            Some("https://example.com/")
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::backend::{home_file, BackendKind, Feed};
+
+// Where each stream last left off, so a restarted pane picks up from there.
+// Each backend, account and feed, keyed like "mastodon/alice/list:security",
+// has a file of its own, so panes in separate processes never write over
+// each other's place.
+
+const STATE_DIR: &str = ".babble_streams";
+
+// Numbers each write's temporary file
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct Cursor {
+    dir: PathBuf,
+    key: String,
+}
+
+/// The newest post a stream has shown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Place {
+    pub(crate) id: String,
+    /// When it was posted, to go by if it can't be found again
+    #[serde(default)]
+    pub(crate) time: Option<DateTime<Utc>>,
+}
+
+impl Cursor {
+    pub(crate) fn new(kind: BackendKind, handle: &str, feed: &Feed) -> Cursor {
+        let feed = match feed {
+            Feed::Home => "home".to_string(),
+            Feed::List(name) => format!("list:{}", name.to_lowercase()),
+            Feed::Me => "me".to_string(),
+        };
+        Cursor {
+            dir: home_file(STATE_DIR),
+            key: format!("{}/{}/{}", kind.name(), handle, feed),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn at(dir: &std::path::Path, key: &str) -> Cursor {
+        Cursor {
+            dir: dir.to_path_buf(),
+            key: key.to_string(),
+        }
+    }
+
+    /// Where the likes in the me feed are up to, which is kept apart from
+    /// the posts
+    pub(crate) fn likes(&self) -> Cursor {
+        Cursor {
+            dir: self.dir.clone(),
+            key: format!("{}/likes", self.key),
+        }
+    }
+
+    /// The newest post shown last time
+    pub(crate) fn load(&self) -> Option<Place> {
+        let file = std::fs::File::open(self.file()).ok()?;
+        serde_json::from_reader(std::io::BufReader::new(file)).ok()
+    }
+
+    // Swapped in whole so a pane never reads half of one
+    pub(crate) fn save(&self, place: &Place) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let file = self.file();
+        let mut tmp = file.clone().into_os_string();
+        tmp.push(format!(
+            ".{}.{}",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        serde_json::to_writer(std::fs::File::create(&tmp)?, place)?;
+        std::fs::rename(&tmp, &file)?;
+        Ok(())
+    }
+
+    // The key with anything that can't go in a file name escaped
+    fn file(&self) -> PathBuf {
+        let name: String = self
+            .key
+            .bytes()
+            .map(|b| match b {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'@' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect();
+        self.dir.join(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_saves_keep_every_cursor() {
+        let dir = std::env::temp_dir().join(format!("babble_cursor_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let columns: Vec<_> = (0..8)
+            .map(|column| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    let cursor = Cursor::at(&dir, &format!("column{}", column));
+                    for id in 0..20 {
+                        let place = Place {
+                            id: id.to_string(),
+                            time: None,
+                        };
+                        cursor.save(&place).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for column in columns {
+            column.join().unwrap();
+        }
+        for column in 0..8 {
+            let cursor = Cursor::at(&dir, &format!("column{}", column));
+            assert_eq!(cursor.load().map(|place| place.id).as_deref(), Some("19"));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn each_key_has_a_file_of_its_own() {
+        let dir = std::env::temp_dir().join(format!("babble_cursor_keys_{}", std::process::id()));
+        let cursor = Cursor::at(&dir, "mastodon/alice@example.social/list:a b");
+        assert_eq!(
+            cursor.file(),
+            dir.join("mastodon%2Falice@example%2Esocial%2Flist%3Aa%20b")
+        );
+        let place = |id: &str| Place {
+            id: id.to_string(),
+            time: None,
+        };
+        cursor.save(&place("1")).unwrap();
+        cursor.likes().save(&place("2")).unwrap();
+        assert_eq!(cursor.load(), Some(place("1")));
+        assert_eq!(cursor.likes().load(), Some(place("2")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,386 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::backend::{Author, Post, ReplyTo, TagKind};
+use crate::hints::Hints;
+use crate::images::Images;
+use crate::text::{close_bidi, normalize};
+use crate::theme::Theme;
+use crate::timestamp::TimeFormat;
+
+mod html;
+mod json;
+mod markdown;
+mod org;
+mod plain;
+mod template;
+mod terminal;
+
+lazy_static::lazy_static! {
+    // From https://www.oreilly.com/library/view/regular-expressions-cookbook/9781449327453/ch08s10.html
+    static ref URL_RE: Regex = Regex::new(r"(?xi)
+        ^
+        (?P<protocol>[a-z][a-z0-9+\-.]*://)
+        (?P<user>[a-z0-9\-._~%!$&'()*+,;=]+@)?
+        (?P<host>[a-z0-9\-._~%]+|\[[a-f0-9:.]+\])
+        (?P<port>:[0-9]+)?
+        (?P<path>/[a-z0-9\-._~%!$&'()*+,;=:@]+)*/?
+        (?P<query>\?[a-z0-9\-._~%!$&'()*+,;=:@/?]*)?
+        (?P<fragment>\#[a-z0-9\-._~%!$&'()*+,;=:@/?]*)?
+        $").unwrap();
+}
+
+pub(crate) trait Formatter {
+    /// Text to print before and after the banner and any login prompts, or
+    /// None to leave the banner out altogether
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        Some(("", ""))
+    }
+
+    /// Printed once before the first post
+    fn header(&self) -> Option<String> {
+        None
+    }
+
+    /// Printed once after the last post when not streaming
+    fn footer(&self) -> Option<String> {
+        None
+    }
+
+    fn format_post(&self, post: &Post) -> String;
+
+    /// Formats everything from one fetch, oldest first
+    fn format_posts(&self, posts: &[&Post]) -> String {
+        posts
+            .iter()
+            .map(|post| self.format_post(post) + "\n")
+            .collect()
+    }
+}
+
+/// Settings from the command line and config that formatters can use
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Options {
+    /// Columns to wrap post text to, 0 to not wrap or None to fit the terminal
+    pub(crate) width: Option<usize>,
+    /// Terminal colors, all empty when color is off
+    pub(crate) theme: Theme,
+    pub(crate) time: TimeFormat,
+    /// Labels to show after links so they can be opened from the stream
+    pub(crate) hints: Option<Arc<Hints>>,
+    /// Draws post images after their text
+    pub(crate) images: Option<Arc<Images>>,
+}
+
+type NewFormatter = fn(&Options) -> Box<dyn Formatter>;
+
+// Every output format, selected by name with --format
+const FORMATTERS: &[(&str, NewFormatter)] = &[
+    ("terminal", terminal::new),
+    ("markdown", markdown::new),
+    ("json", json::new),
+    ("ndjson", json::new_ndjson),
+    ("html", html::new),
+    ("html-dark", html::new_dark),
+    ("org", org::new),
+    ("plain", plain::new),
+];
+
+pub(crate) fn names() -> Vec<&'static str> {
+    FORMATTERS.iter().map(|(name, _)| *name).collect()
+}
+
+pub(crate) fn formatter(name: &str, options: &Options) -> Result<Box<dyn Formatter>> {
+    FORMATTERS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, new)| new(options))
+        .ok_or(anyhow!(
+            "Unknown format {}, expected one of {}",
+            name,
+            names().join(", ")
+        ))
+}
+
+/// A formatter that renders posts through the user's template file
+pub(crate) fn template(path: &Path, options: &Options) -> Result<Box<dyn Formatter>> {
+    template::new(path, options)
+}
+
+/// The parts of a post every format shows, worked out once so formatters
+/// only have to decide how each piece looks.
+pub(crate) struct View<'a> {
+    /// "authored" or "liked" for posts in the `me` feed
+    pub(crate) activity: Option<&'static str>,
+    pub(crate) time: String,
+    pub(crate) context: Option<Context<'a>>,
+    /// The text to show, which for a repost is the original post's
+    pub(crate) body: Vec<Token>,
+    pub(crate) quote: Option<Quote<'a>>,
+}
+
+pub(crate) enum Context<'a> {
+    Repost(&'a Post),
+    Reply(&'a ReplyTo),
+}
+
+pub(crate) struct Quote<'a> {
+    pub(crate) author: &'a Author,
+    pub(crate) body: Vec<Token>,
+}
+
+/// A piece of post text
+pub(crate) enum Token {
+    Text(String),
+    Mention(String),
+    Hashtag(String),
+    Url {
+        url: String,
+        host: String,
+        image: bool,
+    },
+}
+
+impl<'a> View<'a> {
+    pub(crate) fn new(post: &'a Post, time: &TimeFormat) -> View<'a> {
+        let shown = post.reposted.as_deref().unwrap_or(post);
+        View {
+            activity: post.activity.map(|a| a.name()),
+            time: time.format(&post.created_at),
+            context: post
+                .reposted
+                .as_deref()
+                .map(Context::Repost)
+                .or_else(|| post.reply_to.as_ref().map(Context::Reply)),
+            body: tokenize(shown),
+            quote: match post.reposted {
+                Some(_) => None,
+                None => post.quoted.as_deref().map(|qt| Quote {
+                    author: &qt.author,
+                    body: tokenize(qt),
+                }),
+            },
+        }
+    }
+}
+
+// Splits the text at the entity ranges so everything between them, spaces
+// and newlines included, comes through as is
+fn tokenize(post: &Post) -> Vec<Token> {
+    let text = &post.text;
+    let find = |url: &str| text.find(url).map(|start| (start, start + url.len()));
+    let mut entities: Vec<((usize, usize), Token)> = Vec::new();
+    for link in &post.urls {
+        if let Some(range) = link.range.or_else(|| find(&link.url)) {
+            let url = link.expanded_url.as_ref().unwrap_or(&link.url);
+            entities.push((range, url_token(url, false)));
+        }
+    }
+    for media in &post.media {
+        if let Some(range) = media.range.or_else(|| find(&media.url)) {
+            entities.push((range, url_token(&media.media_url, true)));
+        }
+    }
+    for tag in &post.tags {
+        if let Some(word) = text.get(tag.range.0..tag.range.1) {
+            let word = normalize(word);
+            entities.push((
+                tag.range,
+                match tag.kind {
+                    TagKind::Mention => Token::Mention(word),
+                    // Cashtags show like hashtags
+                    TagKind::Hashtag | TagKind::Symbol => Token::Hashtag(word),
+                },
+            ));
+        }
+    }
+    // Earliest first, and the longest of any that start together
+    entities.sort_by_key(|((start, end), _)| (*start, std::cmp::Reverse(*end)));
+
+    let mut tokens = Vec::new();
+    let mut done = 0;
+    for ((start, end), token) in entities {
+        // Overlaps (several photos share one link on Twitter) and ranges
+        // that don't fit the text are dropped
+        if start < done || text.get(start..end).is_none() {
+            continue;
+        }
+        if start > done {
+            tokens.push(Token::Text(normalize(&text[done..start])));
+        }
+        tokens.push(token);
+        done = end;
+    }
+    if done < text.len() {
+        tokens.push(Token::Text(normalize(&text[done..])));
+    }
+    let closing = close_bidi(text);
+    if !closing.is_empty() {
+        tokens.push(Token::Text(closing));
+    }
+    tokens
+}
+
+// Only media entries are images, whatever their urls end in. Bluesky's end
+// in @jpeg, and a link to a picture is still a link.
+fn url_token(url: &str, image: bool) -> Token {
+    Token::Url {
+        host: host(url),
+        image,
+        url: url.to_string(),
+    }
+}
+
+fn host(url: &str) -> String {
+    let parsed_url = URL_RE.captures(url);
+    debug!("Got url parsed {:?}", parsed_url);
+    parsed_url
+        .and_then(|cap| cap.name("host").map(|h| h.as_str().to_string()))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{locate, test_post, Link, Media, Tag};
+
+    fn post(text: &str, urls: &[(&str, (usize, usize))], tags: &[(TagKind, &str)]) -> Post {
+        Post {
+            text: text.to_string(),
+            urls: urls
+                .iter()
+                .map(|&(url, range)| Link {
+                    url: url.to_string(),
+                    display_url: url.to_string(),
+                    expanded_url: None,
+                    range: Some(range),
+                })
+                .collect(),
+            media: vec![],
+            // Found the way the backends find them, from the text itself
+            tags: tags
+                .iter()
+                .filter_map(|&(kind, needle)| {
+                    Some(Tag {
+                        kind,
+                        range: locate(text, (0, 0), needle)?,
+                    })
+                })
+                .collect(),
+            ..test_post("1", chrono::Utc::now())
+        }
+    }
+
+    fn tokens(post: &Post) -> Vec<String> {
+        tokenize(post)
+            .into_iter()
+            .map(|token| match token {
+                Token::Text(text) => format!("text:{}", text),
+                Token::Mention(name) => format!("mention:{}", name),
+                Token::Hashtag(tag) => format!("hashtag:{}", tag),
+                Token::Url { url, host, .. } => format!("url:{} ({})", url, host),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tags_glued_to_punctuation() {
+        let post = post(
+            "(@bob), #rust! and @amy.",
+            &[],
+            &[
+                (TagKind::Mention, "@bob"),
+                (TagKind::Hashtag, "#rust"),
+                (TagKind::Mention, "@amy"),
+            ],
+        );
+        assert_eq!(
+            tokens(&post),
+            vec![
+                "text:(",
+                "mention:@bob",
+                "text:), ",
+                "hashtag:#rust",
+                "text:! and ",
+                "mention:@amy",
+                "text:.",
+            ]
+        );
+    }
+
+    #[test]
+    fn hash_inside_a_url_is_part_of_the_url() {
+        let url = "https://example.com/page#rust";
+        let text = format!("See {} #rust", url);
+        let mut post = post(&text, &[(url, (4, 4 + url.len()))], &[]);
+        // A tag found inside the link overlaps it and is dropped
+        post.tags.push(Tag {
+            kind: TagKind::Hashtag,
+            range: (text.find("#rust").unwrap(), text.find("#rust").unwrap() + 5),
+        });
+        post.tags.push(Tag {
+            kind: TagKind::Hashtag,
+            range: (text.len() - 5, text.len()),
+        });
+        assert_eq!(
+            tokens(&post),
+            vec![
+                "text:See ",
+                "url:https://example.com/page#rust (example.com)",
+                "text: ",
+                "hashtag:#rust",
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_offsets_next_to_emoji() {
+        // 👋 is two UTF-16 units and four bytes, so a range counted in
+        // UTF-16 units lands inside the emoji rather than on the mention
+        let text = "👋 @bob hi";
+        let utf16 = (3, 7);
+        assert!(text.get(utf16.0..utf16.1).is_none());
+        let found = locate(text, utf16, "@bob").unwrap();
+        assert_eq!(&text[found.0..found.1], "@bob");
+
+        let mut post = post(text, &[], &[]);
+        post.tags.push(Tag {
+            kind: TagKind::Mention,
+            range: found,
+        });
+        assert_eq!(tokens(&post), vec!["text:👋 ", "mention:@bob", "text: hi"]);
+    }
+
+    #[test]
+    fn ranges_that_split_a_character_are_dropped() {
+        let mut post = post("👋 @bob", &[], &[]);
+        post.tags.push(Tag {
+            kind: TagKind::Mention,
+            range: (2, 6),
+        });
+        assert_eq!(tokens(&post), vec!["text:👋 @bob"]);
+    }
+
+    #[test]
+    fn only_media_are_images() {
+        let link = "https://example.com/cat.png";
+        let text = format!("{} pic", link);
+        let mut post = post(&text, &[(link, (0, link.len()))], &[]);
+        post.media.push(Media {
+            url: "pic".to_string(),
+            display_url: "pic".to_string(),
+            media_url: "https://cdn.bsky.app/img/feed_fullsize/plain/did/cid@jpeg".to_string(),
+            range: Some((text.len() - 3, text.len())),
+        });
+        let images: Vec<bool> = tokenize(&post)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Url { image, .. } => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images, vec![false, true]);
+    }
+}
@@ -0,0 +1,289 @@
+use super::{Context, Formatter, Options, Token, View};
+use crate::backend::{Author, Post};
+use crate::timestamp::TimeFormat;
+
+// A self-contained page: styles are inline and images are hotlinked, so the
+// output can be published as is.
+
+pub(crate) struct Html {
+    dark: bool,
+    time: TimeFormat,
+}
+
+pub(crate) fn new(options: &Options) -> Box<dyn Formatter> {
+    Box::new(Html {
+        dark: false,
+        time: options.time.clone(),
+    })
+}
+
+pub(crate) fn new_dark(options: &Options) -> Box<dyn Formatter> {
+    Box::new(Html {
+        dark: true,
+        time: options.time.clone(),
+    })
+}
+
+const STYLE: &str = "
+:root { --bg: #fafafa; --fg: #222; --card: #fff; --muted: #777; --link: #1565c0; --tag: #2e7d32; --border: #ddd; }
+body.dark { --bg: #15181c; --fg: #ddd; --card: #1e2329; --muted: #8b98a5; --link: #64b5f6; --tag: #81c784; --border: #38444d; }
+body { background: var(--bg); color: var(--fg); font-family: -apple-system, system-ui, sans-serif; max-width: 42em; margin: 0 auto; padding: 1em; line-height: 1.4; }
+a { color: var(--link); text-decoration: none; }
+a:hover { text-decoration: underline; }
+article { background: var(--card); border: 1px solid var(--border); border-radius: 8px; padding: 0.75em 1em; margin: 0 0 1em; }
+.meta, .context { color: var(--muted); font-size: 0.9em; margin: 0.25em 0; }
+.handle { font-weight: bold; }
+.activity { color: var(--muted); text-transform: uppercase; font-size: 0.75em; letter-spacing: 0.05em; }
+.tag { color: var(--tag); }
+.text { white-space: pre-wrap; overflow-wrap: anywhere; }
+.media { display: flex; flex-wrap: wrap; gap: 0.5em; }
+.media img { max-width: 100%; max-height: 16em; border-radius: 6px; border: 1px solid var(--border); }
+blockquote { border-left: 3px solid var(--border); margin: 0.5em 0; padding: 0 0.75em; }
+";
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// The bare handle or tag in a word like "@someone:" or "#tag,"
+fn entity_name(word: &str) -> &str {
+    word[1..].trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_'))
+}
+
+// Profiles on every network are the author's url with another handle
+fn profile_url(author: &Author, handle: &str) -> Option<String> {
+    author
+        .url
+        .strip_suffix(&author.handle)
+        .filter(|prefix| !author.handle.is_empty() && prefix.starts_with("http"))
+        .map(|prefix| format!("{}{}", prefix, handle))
+}
+
+fn tag_url(author: &Author, tag: &str) -> Option<String> {
+    let origin = author
+        .url
+        .splitn(4, '/')
+        .take(3)
+        .collect::<Vec<_>>()
+        .join("/");
+    if origin.ends_with("twitter.com") || origin.ends_with("bsky.app") {
+        Some(format!("{}/hashtag/{}", origin, tag))
+    } else if profile_url(author, "").is_some() {
+        // Mastodon
+        Some(format!("{}/tags/{}", origin, tag))
+    } else {
+        None
+    }
+}
+
+fn link(url: Option<String>, class: &str, text: &str) -> String {
+    match url {
+        Some(url) => format!(
+            "<a class=\"{}\" href=\"{}\">{}</a>",
+            class,
+            escape(&url),
+            escape(text)
+        ),
+        None => format!("<span class=\"{}\">{}</span>", class, escape(text)),
+    }
+}
+
+/// The text with links, and the images in it to show as thumbnails
+fn htmlify_tweet_text(tokens: &[Token], author: &Author) -> (String, Vec<String>) {
+    let mut images = vec![];
+    let text = tokens
+        .iter()
+        .map(|token| match token {
+            Token::Text(text) => escape(text),
+            Token::Mention(word) => link(profile_url(author, entity_name(word)), "handle", word),
+            Token::Hashtag(word) => link(tag_url(author, entity_name(word)), "tag", word),
+            Token::Url {
+                url,
+                host,
+                image: true,
+            } => {
+                images.push(url.clone());
+                format!("<a href=\"{}\">{}</a>", escape(url), escape(host))
+            }
+            Token::Url { url, host, .. } => format!(
+                "<a href=\"{}\" title=\"{}\">{}</a>",
+                escape(url),
+                escape(url),
+                escape(host)
+            ),
+        })
+        .collect::<String>();
+    (text.trim_end().to_string(), images)
+}
+
+fn thumbnails(images: &[String]) -> String {
+    if images.is_empty() {
+        return "".to_string();
+    }
+    let imgs: String = images
+        .iter()
+        .map(|url| {
+            format!(
+                "<a href=\"{}\"><img src=\"{}\" loading=\"lazy\" alt=\"\"></a>",
+                escape(url),
+                escape(url)
+            )
+        })
+        .collect();
+    format!("<div class=\"media\">{}</div>\n", imgs)
+}
+
+impl Formatter for Html {
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    fn header(&self) -> Option<String> {
+        Some(format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>babble {}</title>\n<style>{}</style>\n</head>\n<body{}>\n",
+            chrono::Local::now().format("%Y-%m-%d"),
+            STYLE,
+            if self.dark { " class=\"dark\"" } else { "" }
+        ))
+    }
+
+    fn footer(&self) -> Option<String> {
+        Some("</body>\n</html>\n".to_string())
+    }
+
+    fn format_post(&self, tweet: &Post) -> String {
+        let view = View::new(tweet, &self.time);
+        let shown = tweet.reposted.as_deref().unwrap_or(tweet);
+        let mut out = format!(
+            "<article id=\"{}\">\n<p>{}<a class=\"handle\" href=\"{}\">@{}</a> {}</p>\n\
+             <p class=\"meta\"><time datetime=\"{}\">{}</time> ♺:{} ♥:{}",
+            escape(&tweet.id),
+            view.activity
+                .map(|a| format!("<span class=\"activity\">{}</span> ", a))
+                .unwrap_or_default(),
+            escape(&tweet.author.url),
+            escape(&tweet.author.handle),
+            escape(&tweet.author.name),
+            tweet.created_at.to_rfc3339(),
+            escape(&view.time),
+            &tweet.repost_count,
+            &tweet.like_count,
+        );
+        if let Some(ref source) = tweet.source {
+            out.push_str(&format!(" <em>via {}</em>", escape(source)));
+        }
+        if let Some(ref place) = tweet.place {
+            out.push_str(&format!(" from {}", escape(place)));
+        }
+        out.push_str("</p>\n");
+
+        match view.context {
+            Some(Context::Repost(rt)) => out.push_str(&format!(
+                "<p class=\"context\">➜ RT <a class=\"handle\" href=\"{}\">@{}</a> {} ♥:{}</p>\n",
+                escape(&rt.author.url),
+                escape(&rt.author.handle),
+                escape(&rt.author.name),
+                &rt.like_count
+            )),
+            Some(Context::Reply(reply)) => out.push_str(&format!(
+                "<p class=\"context\">➜ In reply to <a href=\"{}\">post by @{}</a></p>\n",
+                escape(&reply.url),
+                escape(&reply.handle)
+            )),
+            None => (),
+        }
+
+        let (text, images) = htmlify_tweet_text(&view.body, &shown.author);
+        out.push_str(&format!("<p class=\"text\">{}</p>\n", text));
+        out.push_str(&thumbnails(&images));
+
+        if let Some(ref qt) = view.quote {
+            let (text, images) = htmlify_tweet_text(&qt.body, qt.author);
+            out.push_str(&format!(
+                "<blockquote>\n<p class=\"context\">➜ QT <a class=\"handle\" href=\"{}\">@{}</a> {}</p>\n\
+                 <p class=\"text\">{}</p>\n{}</blockquote>\n",
+                escape(&qt.author.url),
+                escape(&qt.author.handle),
+                escape(&qt.author.name),
+                text,
+                thumbnails(&images)
+            ));
+        }
+        out.push_str("</article>");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{locate, test_post, Tag, TagKind};
+
+    fn post(url: &str, text: &str, tags: &[(TagKind, &str)]) -> Post {
+        let handle = url.rsplit('/').next().unwrap_or("").trim_start_matches('@');
+        Post {
+            author: Author {
+                handle: handle.to_string(),
+                name: "Alice <3".to_string(),
+                url: url.to_string(),
+            },
+            text: text.to_string(),
+            tags: tags
+                .iter()
+                .filter_map(|&(kind, word)| {
+                    Some(Tag {
+                        kind,
+                        range: locate(text, (0, 0), word)?,
+                    })
+                })
+                .collect(),
+            ..test_post("1", chrono::Utc::now())
+        }
+    }
+
+    fn html(post: &Post) -> String {
+        new(&Options::default()).format_post(post)
+    }
+
+    #[test]
+    fn escapes_post_text() {
+        let out = html(&post(
+            "https://twitter.com/alice",
+            "<script>alert(\"x\")</script> & 'so on'",
+            &[],
+        ));
+        assert!(out.contains(
+            "<p class=\"text\">&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; \
+             &amp; &#39;so on&#39;</p>"
+        ));
+        assert!(out.contains("Alice &lt;3"));
+        assert!(!out.contains("<script>"));
+    }
+
+    #[test]
+    fn links_handles_and_tags_on_the_authors_network() {
+        let tags = [(TagKind::Mention, "@bob"), (TagKind::Hashtag, "#rust")];
+        let text = "Hi @bob: #rust, yes";
+
+        let out = html(&post("https://twitter.com/alice", text, &tags));
+        assert!(out.contains(
+            "Hi <a class=\"handle\" href=\"https://twitter.com/bob\">@bob</a>: \
+             <a class=\"tag\" href=\"https://twitter.com/hashtag/rust\">#rust</a>, yes"
+        ));
+
+        let out = html(&post("https://mastodon.social/@alice", text, &tags));
+        assert!(out.contains("href=\"https://mastodon.social/@bob\">@bob</a>"));
+        assert!(out.contains("href=\"https://mastodon.social/tags/rust\">#rust</a>"));
+
+        // Feeds have no profiles to link to
+        let out = html(&post("feeds", text, &tags));
+        assert!(out.contains("<span class=\"handle\">@bob</span>"));
+        assert!(out.contains("<span class=\"tag\">#rust</span>"));
+    }
+}
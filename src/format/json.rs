@@ -0,0 +1,161 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{Formatter, Options};
+use crate::backend::Post;
+
+/// A JSON array per fetch
+pub(crate) struct Json;
+
+pub(crate) fn new(_options: &Options) -> Box<dyn Formatter> {
+    Box::new(Json)
+}
+
+/// One JSON object per line
+pub(crate) struct Ndjson;
+
+pub(crate) fn new_ndjson(_options: &Options) -> Box<dyn Formatter> {
+    Box::new(Ndjson)
+}
+
+// Neither prints the banner so the output stays parseable
+
+impl Formatter for Json {
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    fn format_post(&self, post: &Post) -> String {
+        serde_json::to_string_pretty(&JsonPost::from(post)).unwrap()
+    }
+
+    fn format_posts(&self, posts: &[&Post]) -> String {
+        let json: Vec<JsonPost> = posts.iter().map(|p| JsonPost::from(*p)).collect();
+        serde_json::to_string_pretty(&json).unwrap() + "\n"
+    }
+}
+
+impl Formatter for Ndjson {
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    fn format_post(&self, post: &Post) -> String {
+        serde_json::to_string(&JsonPost::from(post)).unwrap()
+    }
+}
+
+// The schema for --format json and ndjson. It's documented in the README
+// and scripts depend on it, so only ever add fields here.
+
+#[derive(Debug, Serialize)]
+struct JsonPost<'a> {
+    id: &'a str,
+    author: JsonAuthor<'a>,
+    created_at: DateTime<Utc>,
+    text: &'a str,
+    repost_count: u64,
+    like_count: u64,
+    source: Option<&'a str>,
+    place: Option<&'a str>,
+    urls: Vec<JsonUrl<'a>>,
+    media: Vec<JsonMedia<'a>>,
+    reply_to: Option<JsonReplyTo<'a>>,
+    reposted: Option<Box<JsonPost<'a>>>,
+    quoted: Option<Box<JsonPost<'a>>>,
+    activity: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonAuthor<'a> {
+    handle: &'a str,
+    name: &'a str,
+    url: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonUrl<'a> {
+    url: &'a str,
+    display_url: &'a str,
+    expanded_url: &'a str,
+    /// Byte range of `url` in the text
+    indices: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonMedia<'a> {
+    url: &'a str,
+    display_url: &'a str,
+    media_url: &'a str,
+    indices: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonReplyTo<'a> {
+    handle: &'a str,
+    url: &'a str,
+}
+
+impl<'a> From<&'a Post> for JsonPost<'a> {
+    fn from(post: &'a Post) -> JsonPost<'a> {
+        JsonPost {
+            id: &post.id,
+            author: JsonAuthor {
+                handle: &post.author.handle,
+                name: &post.author.name,
+                url: &post.author.url,
+            },
+            created_at: post.created_at,
+            text: &post.text,
+            repost_count: post.repost_count,
+            like_count: post.like_count,
+            source: post.source.as_deref(),
+            place: post.place.as_deref(),
+            urls: post
+                .urls
+                .iter()
+                .map(|u| JsonUrl {
+                    url: &u.url,
+                    display_url: &u.display_url,
+                    expanded_url: u.expanded_url.as_deref().unwrap_or(&u.url),
+                    indices: u.range,
+                })
+                .collect(),
+            media: post
+                .media
+                .iter()
+                .map(|m| JsonMedia {
+                    url: &m.url,
+                    display_url: &m.display_url,
+                    media_url: &m.media_url,
+                    indices: m.range,
+                })
+                .collect(),
+            reply_to: post.reply_to.as_ref().map(|r| JsonReplyTo {
+                handle: &r.handle,
+                url: &r.url,
+            }),
+            reposted: post
+                .reposted
+                .as_ref()
+                .map(|rt| Box::new(JsonPost::from(&**rt))),
+            quoted: post
+                .quoted
+                .as_ref()
+                .map(|qt| Box::new(JsonPost::from(&**qt))),
+            activity: post.activity.map(|a| a.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_batch_is_an_empty_array() {
+        let json = Json.format_posts(&[]);
+        assert_eq!(serde_json::from_str::<Vec<()>>(&json).unwrap().len(), 0);
+        assert_eq!(json, "[]\n");
+    }
+}
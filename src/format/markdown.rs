@@ -0,0 +1,160 @@
+use super::{Context, Formatter, Options, Token, View};
+use crate::backend::Post;
+use crate::timestamp::TimeFormat;
+
+pub(crate) struct Markdown {
+    time: TimeFormat,
+}
+
+pub(crate) fn new(options: &Options) -> Box<dyn Formatter> {
+    Box::new(Markdown {
+        time: options.time.clone(),
+    })
+}
+
+fn markdownify_tweet_text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            // Keep the post's own line breaks as hard breaks
+            Token::Text(text) => escape(text).replace('\n', "  \n"),
+            Token::Mention(word) | Token::Hashtag(word) => format!("**{}**", escape(word)),
+            Token::Url {
+                url,
+                host,
+                image: true,
+            } => format!("![{}]({})", escape(host), escape_url(url)),
+            Token::Url { url, host, .. } => format!("[{}]({})", escape(host), escape_url(url)),
+        })
+        .collect()
+}
+
+// Backslash escapes anything in post text (or names) that markdown would
+// otherwise take as formatting, so a post can't break the document
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    // Only spaces so far on this line, and then only digits
+    let mut line_start = true;
+    let mut numbered = false;
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~' | '|' => {
+                out.push('\\');
+            }
+            // Bullets, and the dot of an ordered list item
+            '-' | '+' | '=' if line_start => out.push('\\'),
+            '.' | ')' if numbered => out.push('\\'),
+            _ => (),
+        }
+        out.push(c);
+        numbered = c.is_ascii_digit() && (line_start || numbered);
+        line_start = c == '\n' || (line_start && c == ' ');
+    }
+    out
+}
+
+fn escape_url(url: &str) -> String {
+    url.replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+impl Formatter for Markdown {
+    // The banner goes in a code block
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        Some(("```\n\n", "```\n\n"))
+    }
+
+    fn format_post(&self, tweet: &Post) -> String {
+        let view = View::new(tweet, &self.time);
+        let header: String = format!(
+            "### {}**[@{}]({})** {} at {} ",
+            view.activity
+                .map(|a| format!("_{}_ ", a))
+                .unwrap_or_default(),
+            escape(&tweet.author.handle),
+            escape_url(&tweet.author.url),
+            escape(&tweet.author.name),
+            &view.time
+        );
+
+        let via: String = tweet
+            .source
+            .as_ref()
+            .map(|s| format!(" _via {}_", escape(s)))
+            .unwrap_or("".to_string());
+        let from: String = tweet
+            .place
+            .as_ref()
+            .map(|p| format!(" from {}", escape(p)))
+            .unwrap_or("".to_string());
+        let meta: String = format!(
+            "{}:{} {}:{}{}{}\n",
+            "♺", &tweet.repost_count, "♥", &tweet.like_count, &via, &from
+        );
+
+        let context = match view.context {
+            Some(Context::Repost(rt)) => format!(
+                "{} **@{}** {} {}:{}\n",
+                "➜ RT",
+                escape(&rt.author.handle),
+                escape(&rt.author.name),
+                "♥",
+                &rt.like_count,
+            ),
+            Some(Context::Reply(reply)) => format!(
+                "{} [tweet by @{}]({})\n",
+                "➜ In reply to",
+                escape(&reply.handle),
+                escape_url(&reply.url),
+            ),
+            None => "".to_string(),
+        };
+
+        let tweet: String = match view.quote {
+            Some(ref qt) => format!(
+                "{}\n--\n{} {} **{}**\n{}\n",
+                markdownify_tweet_text(&view.body),
+                "➜ QT",
+                escape(&qt.author.handle),
+                escape(&qt.author.name),
+                markdownify_tweet_text(&qt.body)
+            ),
+            None => format!("{}\n", markdownify_tweet_text(&view.body)),
+        };
+
+        header + &meta + &context + &tweet //+ blankline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_inline_markup() {
+        assert_eq!(
+            escape("*bold* _it_ `code` [a](b) <b> #tag ~x~ a|b \\"),
+            "\\*bold\\* \\_it\\_ \\`code\\` \\[a\\](b) \\<b\\> \\#tag \\~x\\~ a\\|b \\\\"
+        );
+    }
+
+    #[test]
+    fn escapes_list_markers_only_at_line_start() {
+        assert_eq!(escape("- a\n  + b\n= c"), "\\- a\n  \\+ b\n\\= c");
+        assert_eq!(escape("1. one\n12) two"), "1\\. one\n12\\) two");
+        assert_eq!(escape("a - b, v2.0 (1)"), "a - b, v2.0 (1)");
+    }
+
+    #[test]
+    fn escapes_what_ends_a_link_target() {
+        assert_eq!(
+            escape_url("https://en.wikipedia.org/wiki/Rust (language)"),
+            "https://en.wikipedia.org/wiki/Rust%20%28language%29"
+        );
+        assert_eq!(
+            escape_url("https://example.com/a_b"),
+            "https://example.com/a_b"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use super::{Context, Formatter, Options, Token, View};
+use crate::backend::Post;
+use crate::timestamp::TimeFormat;
+
+pub(crate) struct Org {
+    time: TimeFormat,
+}
+
+pub(crate) fn new(options: &Options) -> Box<dyn Formatter> {
+    Box::new(Org {
+        time: options.time.clone(),
+    })
+}
+
+fn orgify_tweet_text(tokens: &[Token]) -> String {
+    let text: String = tokens
+        .iter()
+        .map(|token| match token {
+            Token::Text(text) => text.clone(),
+            Token::Mention(word) | Token::Hashtag(word) => format!("*{}*", word),
+            // A link with no description shows inline with org-display-inline-images
+            Token::Url {
+                url, image: true, ..
+            } => format!("[[{}]]", url),
+            Token::Url { url, host, .. } => format!("[[{}][{}]]", url, host),
+        })
+        .collect();
+    escape_lines(text.trim_end())
+}
+
+// Post text lines that look like headings or keywords would break the
+// outline, so push them off the first column
+fn escape_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.starts_with('*') || line.starts_with("#+") || line.starts_with(':') {
+                format!(" {}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Property values and headlines have to stay on one line
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn timestamp(post: &Post, time: &TimeFormat) -> String {
+    time.local(&post.created_at)
+        .format("[%Y-%m-%d %a %H:%M]")
+        .to_string()
+}
+
+fn drawer(tweet: &Post, time: &TimeFormat) -> String {
+    let mut props: Vec<(&str, String)> = vec![
+        ("ID", tweet.id.clone()),
+        ("AUTHOR", format!("@{}", &tweet.author.handle)),
+        ("AUTHOR_NAME", one_line(&tweet.author.name)),
+        ("AUTHOR_URL", tweet.author.url.clone()),
+        ("CREATED", timestamp(tweet, time)),
+        ("CREATED_UTC", tweet.created_at.to_rfc3339()),
+        ("REPOSTS", tweet.repost_count.to_string()),
+        ("LIKES", tweet.like_count.to_string()),
+    ];
+    if let Some(activity) = tweet.activity {
+        props.push(("ACTIVITY", activity.name().to_string()));
+    }
+    if let Some(ref source) = tweet.source {
+        props.push(("SOURCE", one_line(source)));
+    }
+    if let Some(ref place) = tweet.place {
+        props.push(("PLACE", one_line(place)));
+    }
+    if let Some(ref reply) = tweet.reply_to {
+        props.push(("REPLY_TO", reply.url.clone()));
+    }
+    if let Some(ref rt) = tweet.reposted {
+        props.push(("REPOST_OF", rt.id.clone()));
+        props.push(("REPOST_AUTHOR", format!("@{}", &rt.author.handle)));
+        props.push(("REPOST_CREATED", timestamp(rt, time)));
+        props.push(("REPOST_LIKES", rt.like_count.to_string()));
+    }
+    if let Some(ref qt) = tweet.quoted {
+        props.push(("QUOTE_OF", qt.id.clone()));
+        props.push(("QUOTE_AUTHOR", format!("@{}", &qt.author.handle)));
+    }
+
+    let mut out = ":PROPERTIES:\n".to_string();
+    for (name, value) in props {
+        out.push_str(&format!(":{}: {}\n", name, value));
+    }
+    out.push_str(":END:\n");
+    out
+}
+
+impl Formatter for Org {
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        Some(("#+begin_example\n", "#+end_example\n\n"))
+    }
+
+    fn header(&self) -> Option<String> {
+        Some("#+STARTUP: inlineimages\n\n".to_string())
+    }
+
+    fn format_post(&self, tweet: &Post) -> String {
+        let view = View::new(tweet, &self.time);
+        let mut out = format!(
+            "* [[{}][@{}]] {} at {}{}\n",
+            &tweet.author.url,
+            &tweet.author.handle,
+            one_line(&tweet.author.name),
+            &view.time,
+            // As a tag so agenda searches can pick out likes
+            view.activity
+                .map(|a| format!(" :{}:", a))
+                .unwrap_or_default(),
+        );
+        out.push_str(&drawer(tweet, &self.time));
+
+        match view.context {
+            Some(Context::Repost(rt)) => out.push_str(&format!(
+                "➜ RT [[{}][@{}]] {} ♥:{}\n",
+                &rt.author.url,
+                &rt.author.handle,
+                one_line(&rt.author.name),
+                &rt.like_count
+            )),
+            Some(Context::Reply(reply)) => out.push_str(&format!(
+                "➜ In reply to [[{}][post by @{}]]\n",
+                &reply.url, &reply.handle
+            )),
+            None => (),
+        }
+
+        out.push_str(&orgify_tweet_text(&view.body));
+        out.push('\n');
+        if let Some(ref qt) = view.quote {
+            out.push_str(&format!(
+                "#+begin_quote\n➜ QT [[{}][@{}]] {}\n{}\n#+end_quote\n",
+                &qt.author.url,
+                &qt.author.handle,
+                one_line(&qt.author.name),
+                orgify_tweet_text(&qt.body)
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{test_post, Activity, Author, Media};
+
+    fn alice(id: &str) -> Post {
+        Post {
+            author: Author {
+                handle: "alice".to_string(),
+                name: "Alice\nSmith".to_string(),
+                url: "https://example.com/alice".to_string(),
+            },
+            ..test_post(id, chrono::Utc::now())
+        }
+    }
+
+    fn org() -> Box<dyn Formatter> {
+        new(&Options::default())
+    }
+
+    #[test]
+    fn headline_links_the_author_and_tags_likes() {
+        let mut post = alice("1");
+        post.activity = Some(Activity::Liked);
+        let out = org().format_post(&post);
+        let headline = out.lines().next().unwrap();
+        assert!(headline.starts_with("* [[https://example.com/alice][@alice]] Alice Smith at "));
+        assert!(headline.ends_with(" :liked:"));
+    }
+
+    #[test]
+    fn drawer_holds_the_post_details() {
+        let mut original = alice("1");
+        original.like_count = 4;
+        let mut post = alice("2");
+        post.author.handle = "bob".to_string();
+        post.repost_count = 3;
+        post.source = Some("web\nclient".to_string());
+        post.reposted = Some(Box::new(original));
+
+        let out = org().format_post(&post);
+        let drawer: Vec<&str> = out
+            .lines()
+            .skip_while(|line| *line != ":PROPERTIES:")
+            .take_while(|line| *line != ":END:")
+            .collect();
+        for property in &[
+            ":ID: 2",
+            ":AUTHOR: @bob",
+            ":AUTHOR_NAME: Alice Smith",
+            ":AUTHOR_URL: https://example.com/alice",
+            &format!(":CREATED_UTC: {}", post.created_at.to_rfc3339()),
+            ":REPOSTS: 3",
+            ":LIKES: 0",
+            ":SOURCE: web client",
+            ":REPOST_OF: 1",
+            ":REPOST_AUTHOR: @alice",
+            ":REPOST_LIKES: 4",
+        ] {
+            assert!(drawer.contains(property), "{} missing", property);
+        }
+        assert!(!drawer.iter().any(|line| line.starts_with(":PLACE:")));
+    }
+
+    #[test]
+    fn escapes_lines_that_would_break_the_outline() {
+        assert_eq!(
+            escape_lines("* heading\n#+TITLE: x\n:DRAWER:\nfine * here"),
+            " * heading\n #+TITLE: x\n :DRAWER:\nfine * here"
+        );
+        let mut post = alice("1");
+        post.text = "first\n** not a heading".to_string();
+        assert!(org()
+            .format_post(&post)
+            .contains("\nfirst\n ** not a heading\n"));
+    }
+
+    #[test]
+    fn images_are_bare_links() {
+        let mut post = alice("1");
+        post.text = "look pic".to_string();
+        post.media.push(Media {
+            url: "pic".to_string(),
+            display_url: "pic".to_string(),
+            media_url: "https://example.com/cat.jpg".to_string(),
+            range: Some((5, 8)),
+        });
+        assert!(org()
+            .format_post(&post)
+            .contains("look [[https://example.com/cat.jpg]]\n"));
+    }
+}
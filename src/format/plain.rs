@@ -0,0 +1,79 @@
+use super::{Context, Formatter, Options, Token, View};
+use crate::backend::Post;
+use crate::text;
+use crate::timestamp::TimeFormat;
+
+// The terminal layout without any escape codes, for logs and dumb terminals
+
+pub(crate) struct Plain {
+    width: Option<usize>,
+    time: TimeFormat,
+}
+
+pub(crate) fn new(options: &Options) -> Box<dyn Formatter> {
+    Box::new(Plain {
+        width: options.width,
+        time: options.time.clone(),
+    })
+}
+
+impl Plain {
+    fn plain_text(&self, tokens: &[Token]) -> String {
+        let text: String = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Text(text) | Token::Mention(text) | Token::Hashtag(text) => text.as_str(),
+                Token::Url { url, .. } => url.as_str(),
+            })
+            .collect();
+        match text::wrap_width(self.width) {
+            Some(width) => text::wrap(&[(text, "")], width, "  ", ""),
+            None => text,
+        }
+    }
+}
+
+impl Formatter for Plain {
+    fn format_post(&self, tweet: &Post) -> String {
+        let view = View::new(tweet, &self.time);
+        let mut out = format!(
+            "{}@{} {} at {} ♺:{} ♥:{}",
+            view.activity
+                .map(|a| format!("[{}] ", a))
+                .unwrap_or_default(),
+            &tweet.author.handle,
+            &tweet.author.name,
+            &view.time,
+            &tweet.repost_count,
+            &tweet.like_count
+        );
+        if let Some(ref source) = tweet.source {
+            out.push_str(&format!(" via {}", source));
+        }
+        if let Some(ref place) = tweet.place {
+            out.push_str(&format!(" from {}", place));
+        }
+        out.push('\n');
+
+        match view.context {
+            Some(Context::Repost(rt)) => out.push_str(&format!(
+                "➜ RT @{} {} ♥:{}\n",
+                &rt.author.handle, &rt.author.name, &rt.like_count
+            )),
+            Some(Context::Reply(reply)) => out.push_str(&format!("➜ In reply to {}\n", &reply.url)),
+            None => (),
+        }
+
+        out.push_str(&self.plain_text(&view.body));
+        out.push('\n');
+        if let Some(ref qt) = view.quote {
+            out.push_str(&format!(
+                "--\n➜ QT {} {}\n{}\n",
+                &qt.author.handle,
+                &qt.author.name,
+                self.plain_text(&qt.body)
+            ));
+        }
+        out
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::path::Path;
+use tinytemplate::TinyTemplate;
+
+use super::{Context, Formatter, Options, Token, View};
+use crate::backend::Post;
+use crate::timestamp::TimeFormat;
+
+// Renders each post through a user supplied TinyTemplate file. The variables
+// are documented in the README, so only ever add to TemplatePost.
+
+pub(crate) struct Template {
+    template: TinyTemplate<'static>,
+    time: TimeFormat,
+}
+
+pub(crate) fn new(path: &Path, options: &Options) -> Result<Box<dyn Formatter>> {
+    // TinyTemplate borrows its source, and there's one template for the whole
+    // run, so the source lives as long as the program
+    let source: &'static str = Box::leak(std::fs::read_to_string(path)?.into_boxed_str());
+    let mut template = TinyTemplate::new();
+    template.set_default_formatter(&tinytemplate::format_unescaped);
+    // Syntax errors and fields that don't exist are reported up front rather
+    // than as an empty line for every post
+    template.add_template("post", source)?;
+    for sample in &[sample(false), sample(true)] {
+        template
+            .render("post", sample)
+            .map_err(|err| anyhow!("{} doesn't render: {}", path.display(), err))?;
+    }
+    Ok(Box::new(Template {
+        template,
+        time: options.time.clone(),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct TemplatePost<'a> {
+    id: &'a str,
+    handle: &'a str,
+    name: &'a str,
+    author_url: &'a str,
+    time: &'a str,
+    timestamp: String,
+    repost_count: u64,
+    like_count: u64,
+    source: Option<&'a str>,
+    place: Option<&'a str>,
+    /// Text with links expanded, the original post's for a repost
+    text: String,
+    urls: Vec<String>,
+    media: Vec<&'a str>,
+    reply_to: Option<TemplateReplyTo<'a>>,
+    repost: Option<TemplateRepost<'a>>,
+    quote: Option<TemplateQuote<'a>>,
+    activity: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateReplyTo<'a> {
+    handle: &'a str,
+    url: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateRepost<'a> {
+    handle: &'a str,
+    name: &'a str,
+    like_count: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateQuote<'a> {
+    handle: &'a str,
+    name: &'a str,
+    text: String,
+}
+
+// A post with every field filled in, or with the optional ones left out
+fn sample(full: bool) -> TemplatePost<'static> {
+    let post = TemplatePost {
+        id: "1",
+        handle: "alice",
+        name: "Alice",
+        author_url: "https://example.com/alice",
+        time: "1 minute ago",
+        timestamp: "2021-06-01T12:00:00+00:00".to_string(),
+        repost_count: 0,
+        like_count: 0,
+        source: Some("web"),
+        place: Some("Paris"),
+        text: "Hello".to_string(),
+        urls: vec![],
+        media: vec![],
+        reply_to: Some(TemplateReplyTo {
+            handle: "bob",
+            url: "https://example.com/bob/2",
+        }),
+        repost: Some(TemplateRepost {
+            handle: "carol",
+            name: "Carol",
+            like_count: 0,
+        }),
+        quote: Some(TemplateQuote {
+            handle: "dave",
+            name: "Dave",
+            text: "Quoted".to_string(),
+        }),
+        activity: Some("authored"),
+    };
+    if full {
+        return post;
+    }
+    TemplatePost {
+        source: None,
+        place: None,
+        reply_to: None,
+        repost: None,
+        quote: None,
+        activity: None,
+        ..post
+    }
+}
+
+fn expanded_text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::Text(text) | Token::Mention(text) | Token::Hashtag(text) => text.as_str(),
+            Token::Url { url, .. } => url.as_str(),
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+impl Formatter for Template {
+    fn banner_fence(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    fn format_post(&self, post: &Post) -> String {
+        let view = View::new(post, &self.time);
+        let shown = post.reposted.as_deref().unwrap_or(post);
+        let context = TemplatePost {
+            id: &post.id,
+            handle: &post.author.handle,
+            name: &post.author.name,
+            author_url: &post.author.url,
+            time: &view.time,
+            timestamp: post.created_at.to_rfc3339(),
+            repost_count: post.repost_count,
+            like_count: post.like_count,
+            source: post.source.as_deref(),
+            place: post.place.as_deref(),
+            text: expanded_text(&view.body),
+            urls: view
+                .body
+                .iter()
+                .filter_map(|token| match token {
+                    Token::Url {
+                        url, image: false, ..
+                    } => Some(url.clone()),
+                    _ => None,
+                })
+                .collect(),
+            media: shown.media.iter().map(|m| m.media_url.as_str()).collect(),
+            reply_to: match view.context {
+                Some(Context::Reply(reply)) => Some(TemplateReplyTo {
+                    handle: &reply.handle,
+                    url: &reply.url,
+                }),
+                _ => None,
+            },
+            repost: match view.context {
+                Some(Context::Repost(rt)) => Some(TemplateRepost {
+                    handle: &rt.author.handle,
+                    name: &rt.author.name,
+                    like_count: rt.like_count,
+                }),
+                _ => None,
+            },
+            quote: view.quote.as_ref().map(|qt| TemplateQuote {
+                handle: &qt.author.handle,
+                name: &qt.author.name,
+                text: expanded_text(&qt.body),
+            }),
+            activity: view.activity,
+        };
+
+        self.template
+            .render("post", &context)
+            .unwrap_or_else(|err| {
+                error!("Failed to render template: {}", err);
+                "".to_string()
+            })
+    }
+
+    // Templates say exactly what goes between posts
+    fn format_posts(&self, posts: &[&Post]) -> String {
+        posts.iter().map(|post| self.format_post(post)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{test_post, Author, ReplyTo};
+
+    fn load(name: &str, source: &str) -> Result<Box<dyn Formatter>> {
+        let path = std::env::temp_dir().join(format!(
+            "babble_template_{}_{}.txt",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, source).unwrap();
+        new(&path, &Options::default())
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_up_front() {
+        assert!(load("good", "{handle}: {text}\n").is_ok());
+        assert!(load("bad", "{{ if reply_to }}{text}\n").is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_reported_up_front() {
+        assert!(load("typo", "{handel}: {text}\n").is_err());
+        // Fields that are empty when unknown can be used as they are
+        assert!(load("plain", "{source} {place} {activity}\n").is_ok());
+        // Fine when there's a quote, but not on every other post
+        assert!(load("unguarded", "{quote.text}\n").is_err());
+        assert!(load("guarded", "{{ if quote }}{quote.text}{{ endif }}\n").is_ok());
+    }
+
+    fn author(handle: &str) -> Author {
+        Author {
+            handle: handle.to_string(),
+            name: handle.to_uppercase(),
+            url: format!("https://example.com/{}", handle),
+        }
+    }
+
+    #[test]
+    fn renders_reply_repost_and_quote_context() {
+        let template = load(
+            "context",
+            "{{ if reply_to }}[re {reply_to.handle}] {{ endif }}\
+             {{ if repost }}[rt {repost.handle}] {{ endif }}\
+             {handle}: {text}\
+             {{ if quote }} [qt {quote.name}: {quote.text}]{{ endif }}\n",
+        )
+        .unwrap();
+        let now = chrono::Utc::now();
+
+        let mut reply = test_post("1", now);
+        reply.author = author("alice");
+        reply.reply_to = Some(ReplyTo {
+            handle: "bob".to_string(),
+            url: "https://example.com/bob/0".to_string(),
+        });
+
+        let mut original = test_post("2", now);
+        original.author = author("carol");
+        let mut repost = test_post("3", now);
+        repost.author = author("alice");
+        repost.reposted = Some(Box::new(original));
+
+        let mut quoted = test_post("4", now);
+        quoted.author = author("dave");
+        let mut quote = test_post("5", now);
+        quote.author = author("alice");
+        quote.quoted = Some(Box::new(quoted));
+
+        assert_eq!(
+            template.format_posts(&[&reply, &repost, &quote]),
+            "[re bob] alice: Post 1\n\
+             [rt carol] alice: Post 2\n\
+             alice: Post 5 [qt DAVE: Post 4]\n"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+use termion::style;
+
+use super::{Context, Formatter, Options, Token, View};
+use crate::backend::Post;
+use crate::hints::Hints;
+use crate::images::Images;
+use crate::text;
+use crate::theme::Theme;
+use crate::timestamp::TimeFormat;
+
+pub(crate) struct ColorConfig {
+    color_user: String,
+    color_time: String,
+    color_url: String,
+    color_meta: String,
+    color_hash: String,
+}
+impl ColorConfig {
+    fn colorize(color: &str, s: &str) -> String {
+        if color.is_empty() {
+            s.to_string()
+        } else {
+            format!("{}{}{}", &color, s, style::Reset)
+        }
+    }
+    fn user(&self, s: &str) -> String {
+        Self::colorize(&self.color_user, s)
+    }
+    fn time(&self, s: &str) -> String {
+        Self::colorize(&self.color_time, s)
+    }
+    fn url(&self, s: &str) -> String {
+        Self::colorize(&self.color_url, s)
+    }
+    fn meta(&self, s: &str) -> String {
+        Self::colorize(&self.color_meta, s)
+    }
+}
+
+impl From<&Theme> for ColorConfig {
+    fn from(theme: &Theme) -> ColorConfig {
+        ColorConfig {
+            color_user: theme.user.clone(),
+            color_time: theme.time.clone(),
+            color_url: theme.url.clone(),
+            color_meta: theme.meta.clone(),
+            color_hash: theme.hashtag.clone(),
+        }
+    }
+}
+
+pub(crate) struct Terminal {
+    color_config: ColorConfig,
+    width: Option<usize>,
+    time: TimeFormat,
+    hints: Option<Arc<Hints>>,
+    images: Option<Arc<Images>>,
+}
+
+pub(crate) fn new(options: &Options) -> Box<dyn Formatter> {
+    Box::new(Terminal {
+        color_config: ColorConfig::from(&options.theme),
+        width: options.width,
+        time: options.time.clone(),
+        hints: options.hints.clone(),
+        images: options.images.clone(),
+    })
+}
+
+// Post and quote text sits under its header when wrapped
+const INDENT: &str = "  ";
+
+impl Terminal {
+    fn colorize_tweet_text(&self, tokens: &[Token]) -> String {
+        let mut spans: Vec<(String, &str)> = vec![];
+        for token in tokens {
+            match token {
+                Token::Text(text) => spans.push((text.clone(), "")),
+                Token::Mention(mention) => {
+                    spans.push((mention.clone(), self.color_config.color_user.as_str()))
+                }
+                Token::Hashtag(hashtag) => {
+                    spans.push((hashtag.clone(), self.color_config.color_hash.as_str()))
+                }
+                Token::Url { url, .. } => {
+                    spans.push((url.clone(), self.color_config.color_url.as_str()));
+                    if let Some(ref hints) = self.hints {
+                        spans.push((
+                            format!(" [{}]", hints.label(url)),
+                            self.color_config.color_meta.as_str(),
+                        ));
+                    }
+                }
+            }
+        }
+        match text::wrap_width(self.width) {
+            Some(width) => text::wrap(&spans, width, INDENT, style::Reset.as_ref()),
+            None => spans
+                .iter()
+                .map(|(text, color)| ColorConfig::colorize(color, text))
+                .collect(),
+        }
+    }
+}
+
+impl Terminal {
+    // Each of the post's images under its text, for the ones that could be
+    // fetched and drawn
+    fn draw_images(&self, tokens: &[Token]) -> String {
+        let images = match self.images {
+            Some(ref images) => images,
+            None => return "".to_string(),
+        };
+        let cols = text::wrap_width(self.width)
+            .unwrap_or(usize::MAX)
+            .saturating_sub(INDENT.len());
+        tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Url {
+                    url, image: true, ..
+                } => images.render(url, cols),
+                _ => None,
+            })
+            .map(|drawn| {
+                drawn
+                    .lines()
+                    .map(|line| format!("{}{}\n", INDENT, line))
+                    .collect::<String>()
+            })
+            .collect()
+    }
+}
+
+impl Formatter for Terminal {
+    // Preferred format:
+    // @handle name at time
+    // ♺:numrts ♥:numhearts via source from place
+    // ➜ in reply to @handle OR
+    // ➜ RT @handle
+    //   tweet text indented
+    // ➜ QT @handle
+    //   quoted text indented
+    // (blank line)
+    fn format_post(&self, tweet: &Post) -> String {
+        let view = View::new(tweet, &self.time);
+        let activity: String = view
+            .activity
+            .map(|a| format!("{} ", self.color_config.meta(&format!("[{}]", a))))
+            .unwrap_or_default();
+        let header: String = format!(
+            "{}@{} {} at {} ",
+            &activity,
+            self.color_config.user(&tweet.author.handle),
+            &tweet.author.name,
+            self.color_config.time(&view.time)
+        );
+
+        let via: String = tweet
+            .source
+            .as_ref()
+            .map(|s| format!(" via {}", s))
+            .unwrap_or("".to_string());
+        let from: String = tweet
+            .place
+            .as_ref()
+            .map(|p| format!(" from {}", p))
+            .unwrap_or("".to_string());
+        let meta: String = format!(
+            "{}:{} {}:{}{}{}\n",
+            self.color_config.meta("♺"),
+            &tweet.repost_count,
+            self.color_config.meta("♥"),
+            &tweet.like_count,
+            &via,
+            &from
+        );
+
+        let context = match view.context {
+            Some(Context::Repost(rt)) => format!(
+                "{} @{} {} {}:{}\n",
+                self.color_config.meta("➜ RT"),
+                self.color_config.user(&rt.author.handle),
+                &rt.author.name,
+                self.color_config.meta("♥"),
+                &rt.like_count,
+            ),
+            Some(Context::Reply(reply)) => format!(
+                "{} {}\n",
+                self.color_config.meta("➜ In reply to"),
+                self.color_config.url(&reply.url),
+            ),
+            None => "".to_string(),
+        };
+
+        // TODO: make simpler supporting functions. For example for name display.
+        // TODO: merge line two up to line one and ditch via url
+        // TODO: single blank between tweets. Make sure \n\n* becomes just \n
+
+        let tweet: String = match view.quote {
+            Some(ref qt) => format!(
+                "{}\n{}--\n{} {} {}\n{}\n{}",
+                self.colorize_tweet_text(&view.body),
+                self.draw_images(&view.body),
+                self.color_config.meta("➜ QT"),
+                self.color_config.user(&qt.author.handle),
+                &qt.author.name,
+                self.colorize_tweet_text(&qt.body),
+                self.draw_images(&qt.body)
+            ),
+            None => format!(
+                "{}\n{}",
+                self.colorize_tweet_text(&view.body),
+                self.draw_images(&view.body)
+            ),
+        };
+
+        header + &meta + &context + &tweet //+ blankline
+    }
+}
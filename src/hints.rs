@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Short labels like [a] or [bc] shown after links while streaming, so a link
+// can be opened or previewed by typing its label. Labels are handed out in
+// order and reused once they run out, so the last few hundred links stay
+// reachable.
+
+// Leaves out q, r and p so labels don't clash with the stream's keys
+const ALPHABET: &[u8] = b"abcdefghijklmnostuvwxyz";
+
+#[derive(Debug, Default)]
+pub(crate) struct Hints {
+    labels: Mutex<Labels>,
+}
+
+#[derive(Debug, Default)]
+struct Labels {
+    next: usize,
+    urls: HashMap<String, String>,
+}
+
+impl Hints {
+    /// The label for a link, the same one again if it was labeled recently
+    pub(crate) fn label(&self, url: &str) -> String {
+        let mut labels = self.labels.lock().unwrap();
+        if let Some((label, _)) = labels.urls.iter().find(|(_, u)| *u == url) {
+            return label.clone();
+        }
+        let label = label_for(labels.next);
+        labels.next = (labels.next + 1) % (ALPHABET.len() * (ALPHABET.len() + 1));
+        labels.urls.insert(label.clone(), url.to_string());
+        label
+    }
+
+    pub(crate) fn url(&self, label: &str) -> Option<String> {
+        self.labels.lock().unwrap().urls.get(label).cloned()
+    }
+
+    /// Whether some label starts with these letters
+    pub(crate) fn is_prefix(&self, typed: &str) -> bool {
+        self.labels
+            .lock()
+            .unwrap()
+            .urls
+            .keys()
+            .any(|label| label.starts_with(typed))
+    }
+}
+
+// a to z and then aa, ab and so on
+fn label_for(n: usize) -> String {
+    let len = ALPHABET.len();
+    if n < len {
+        (ALPHABET[n] as char).to_string()
+    } else {
+        let n = n - len;
+        format!("{}{}", ALPHABET[n / len] as char, ALPHABET[n % len] as char)
+    }
+}
+
+/// Which letters can be part of a label
+pub(crate) fn is_label_char(c: char) -> bool {
+    c.is_ascii() && ALPHABET.contains(&(c as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_run_through_the_alphabet_then_pairs() {
+        let labels: Vec<String> = (0..26).map(label_for).collect();
+        assert_eq!(labels.concat(), "abcdefghijklmnostuvwxyzaaabac");
+        assert_eq!(label_for(23 + 23), "ba");
+        assert_eq!(label_for(551), "zz");
+        assert!(!labels.iter().any(|l| l.contains(|c| "pqr".contains(c))));
+    }
+
+    #[test]
+    fn labels_wrap_around_after_552() {
+        let hints = Hints::default();
+        let labels: Vec<String> = (0..552)
+            .map(|i| hints.label(&format!("https://example.com/{}", i)))
+            .collect();
+        assert_eq!(labels[551], "zz");
+        let mut unique = labels.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 552);
+
+        // The oldest label goes to the next link
+        assert_eq!(hints.label("https://example.com/new"), "a");
+        assert_eq!(hints.url("a").as_deref(), Some("https://example.com/new"));
+        assert_eq!(hints.url("b").as_deref(), Some("https://example.com/1"));
+    }
+
+    #[test]
+    fn a_link_keeps_its_label() {
+        let hints = Hints::default();
+        assert_eq!(hints.label("https://example.com/1"), "a");
+        assert_eq!(hints.label("https://example.com/2"), "b");
+        assert_eq!(hints.label("https://example.com/1"), "a");
+        assert!(hints.is_prefix("b"));
+        assert!(!hints.is_prefix("c"));
+    }
+}
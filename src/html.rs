@@ -0,0 +1,157 @@
+use crate::backend::{Link, Tag, TagKind};
+
+// Just enough HTML handling to turn the markup some networks send for post
+// bodies into the plain text plus links that the renderers expect.
+
+/// Converts an HTML fragment into plain text, keeping paragraph and line
+/// breaks and collecting the target of every link along with where mentions
+/// and hashtags are.
+pub(crate) fn to_text(html: &str) -> (String, Vec<Link>, Vec<Tag>) {
+    let mut text = String::new();
+    let mut links: Vec<Link> = Vec::new();
+    let mut tags: Vec<Tag> = Vec::new();
+    // href of the anchor we're inside of and where its text started
+    let mut anchor: Option<(String, usize)> = None;
+    let mut rest = html;
+
+    while let Some(open) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..open]));
+        let close = match rest[open..].find('>') {
+            Some(close) => open + close,
+            None => {
+                rest = &rest[open..];
+                break;
+            }
+        };
+        let tag = &rest[open + 1..close];
+        let name = tag_name(tag);
+        match name.as_str() {
+            "br" => text.push('\n'),
+            "p" if !tag.starts_with('/') && !text.is_empty() => text.push_str("\n\n"),
+            "a" if !tag.starts_with('/') => {
+                anchor = attribute(tag, "href").map(|href| (href, text.len()));
+            }
+            "a" => {
+                if let Some((href, start)) = anchor.take() {
+                    let display = text[start..].to_string();
+                    let range = (start, text.len());
+                    if display.starts_with('@') {
+                        tags.push(Tag {
+                            kind: TagKind::Mention,
+                            range,
+                        });
+                    } else if display.starts_with('#') {
+                        tags.push(Tag {
+                            kind: TagKind::Hashtag,
+                            range,
+                        });
+                    } else if !display.is_empty() {
+                        links.push(Link {
+                            url: display.clone(),
+                            display_url: display,
+                            expanded_url: Some(href),
+                            range: Some(range),
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+        rest = &rest[close + 1..];
+    }
+    text.push_str(&decode_entities(rest));
+
+    // Trimming moves everything up by the leading whitespace
+    let lead = text.len() - text.trim_start().len();
+    let trimmed = text.trim().to_string();
+    let shift = |(start, end): (usize, usize)| {
+        if start >= lead && end - lead <= trimmed.len() {
+            Some((start - lead, end - lead))
+        } else {
+            None
+        }
+    };
+    for link in links.iter_mut() {
+        link.range = link.range.and_then(shift);
+    }
+    let tags = tags
+        .into_iter()
+        .filter_map(|tag| {
+            Some(Tag {
+                range: shift(tag.range)?,
+                ..tag
+            })
+        })
+        .collect();
+
+    (trimmed, links, tags)
+}
+
+/// The title and description of a web page, preferring the Open Graph ones
+/// sites write for link previews
+pub(crate) fn summary(page: &str) -> (Option<String>, Option<String>) {
+    let mut title: Option<String> = None;
+    let mut og_title: Option<String> = None;
+    let mut description: Option<String> = None;
+    let mut og_description: Option<String> = None;
+    let mut rest = page;
+
+    while let Some(open) = rest.find('<') {
+        let close = match rest[open..].find('>') {
+            Some(close) => open + close,
+            None => break,
+        };
+        let tag = &rest[open + 1..close];
+        match tag_name(tag).as_str() {
+            "title" if !tag.starts_with('/') && title.is_none() => {
+                let end = rest[close..]
+                    .find("</")
+                    .map_or(rest.len(), |end| close + end);
+                title = Some(decode_entities(&rest[close + 1..end]));
+            }
+            "meta" => {
+                let content = attribute(tag, "content");
+                match attribute(tag, "property")
+                    .or_else(|| attribute(tag, "name"))
+                    .as_deref()
+                {
+                    Some("og:title") => og_title = content,
+                    Some("og:description") => og_description = content,
+                    Some("description") => description = content,
+                    _ => (),
+                }
+            }
+            // Everything needed is in the head
+            "body" => break,
+            _ => (),
+        }
+        rest = &rest[close + 1..];
+    }
+
+    let one_line = |s: Option<String>| {
+        s.map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|s| !s.is_empty())
+    };
+    (
+        one_line(og_title.or(title)),
+        one_line(og_description.or(description)),
+    )
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = tag[start..].find('"')? + start;
+    Some(decode_entities(&tag[start..end]))
+}
+
+fn decode_entities(s: &str) -> String {
+    html_escape::decode_html_entities(s).into_owned()
+}
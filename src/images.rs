@@ -0,0 +1,469 @@
+use anyhow::{anyhow, Result};
+use image::io::{Limits, Reader};
+use image::{DynamicImage, RgbImage};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use termion::color;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::backend::Post;
+
+// Draws post images in the terminal with the kitty or iTerm inline image
+// protocols, sixel, or colored half blocks that work in any terminal with
+// 256 colors. Images are downloaded before a batch of posts is formatted and
+// kept on disk, so formatting stays synchronous and reruns are quick. When
+// an image can't be fetched or drawn the post just shows its link, and a
+// slow host only holds a batch up for PREFETCH_SECS.
+
+// Images bigger than this aren't downloaded
+const MAX_BYTES: usize = 5 * 1024 * 1024;
+// Nor decoded when they'd unpack to more than this, as a small file can
+// hold a huge bitmap
+const MAX_PIXELS_ACROSS: u32 = 10_000;
+const MAX_DECODED_BYTES: u64 = 256 * 1024 * 1024;
+// The most terminal cells an image takes up
+const MAX_COLS: usize = 60;
+const MAX_ROWS: usize = 20;
+// Files kept in the cache, the least recently downloaded removed first
+const MAX_CACHED: usize = 500;
+// Cell size in pixels when the terminal doesn't say
+const CELL_PIXELS: (usize, usize) = (10, 20);
+// Downloads running at once
+const MAX_DOWNLOADS: usize = 4;
+const CONNECT_SECS: u64 = 5;
+// The longest one download may take
+const DOWNLOAD_SECS: u64 = 15;
+// The longest a batch of posts waits for its images
+const PREFETCH_SECS: u64 = 20;
+
+/// --images setting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ImageMode {
+    Never,
+    /// The best the terminal looks like it can do
+    Auto,
+    Use(Protocol),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Protocol {
+    Kitty,
+    Iterm,
+    Sixel,
+    Blocks,
+}
+
+impl FromStr for ImageMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ImageMode> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(ImageMode::Never),
+            "auto" => Ok(ImageMode::Auto),
+            "kitty" => Ok(ImageMode::Use(Protocol::Kitty)),
+            "iterm" => Ok(ImageMode::Use(Protocol::Iterm)),
+            "sixel" => Ok(ImageMode::Use(Protocol::Sixel)),
+            "blocks" => Ok(ImageMode::Use(Protocol::Blocks)),
+            _ => Err(anyhow!(
+                "Expected never, auto, kitty, iterm, sixel or blocks, not {}",
+                s
+            )),
+        }
+    }
+}
+
+// Going by what terminals say about themselves. Inside tmux the escape
+// codes of the image protocols don't get through, but blocks do.
+fn detect() -> Protocol {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    if !var("TMUX").is_empty() {
+        Protocol::Blocks
+    } else if var("TERM").contains("kitty") || !var("KITTY_WINDOW_ID").is_empty() {
+        Protocol::Kitty
+    } else if var("TERM_PROGRAM") == "iTerm.app"
+        || var("TERM_PROGRAM") == "WezTerm"
+        || var("LC_TERMINAL") == "iTerm2"
+    {
+        Protocol::Iterm
+    } else if var("TERM").contains("foot") || var("TERM").contains("mlterm") {
+        Protocol::Sixel
+    } else {
+        Protocol::Blocks
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Images {
+    protocol: Protocol,
+    client: reqwest::Client,
+    dir: PathBuf,
+}
+
+impl Images {
+    /// None when images are off or output isn't going to a terminal, and
+    /// for blocks when color is off
+    pub(crate) fn new(mode: ImageMode, color: bool) -> Option<Images> {
+        let protocol = match mode {
+            ImageMode::Never => return None,
+            ImageMode::Auto => detect(),
+            ImageMode::Use(protocol) => protocol,
+        };
+        if !termion::is_tty(&std::io::stdout()) || (protocol == Protocol::Blocks && !color) {
+            return None;
+        }
+        let dir = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(cache) if !cache.is_empty() => PathBuf::from(cache),
+            _ => crate::backend::home_file(".cache"),
+        }
+        .join("babble")
+        .join("images");
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_SECS))
+            .timeout(Duration::from_secs(DOWNLOAD_SECS))
+            .build()
+            .ok()?;
+        Some(Images {
+            protocol,
+            client,
+            dir,
+        })
+    }
+
+    /// Downloads the images in these posts that aren't cached yet, a few
+    /// at a time. Failures are only logged since those posts fall back to
+    /// links, and whatever isn't in after PREFETCH_SECS is given up on.
+    pub(crate) async fn prefetch(&self, posts: &[&Post]) {
+        let mut urls: Vec<&str> = vec![];
+        for post in posts {
+            let shown = post.reposted.as_deref().unwrap_or(post);
+            let media = shown
+                .media
+                .iter()
+                .chain(shown.quoted.iter().flat_map(|qt| qt.media.iter()));
+            for media in media {
+                let url = media.media_url.as_str();
+                if !urls.contains(&url) && !self.path(url).exists() {
+                    urls.push(url);
+                }
+            }
+        }
+        if urls.is_empty() {
+            return;
+        }
+
+        let slots = Arc::new(Semaphore::new(MAX_DOWNLOADS));
+        let mut downloads = JoinSet::new();
+        for url in urls {
+            let (client, slots, url) = (self.client.clone(), slots.clone(), url.to_string());
+            downloads.spawn(async move {
+                let _slot = slots.acquire().await;
+                let bytes = download(&client, &url).await;
+                (url, bytes)
+            });
+        }
+        let stored = async {
+            while let Some(Ok((url, bytes))) = downloads.join_next().await {
+                match bytes {
+                    Ok(bytes) => {
+                        if let Err(err) = self.store(&self.path(&url), &bytes) {
+                            debug!("Couldn't cache {}: {}", url, err);
+                        }
+                    }
+                    Err(err) => debug!("Couldn't fetch {}: {}", url, err),
+                }
+            }
+        };
+        if tokio::time::timeout(Duration::from_secs(PREFETCH_SECS), stored)
+            .await
+            .is_err()
+        {
+            debug!("Gave up on {} images", downloads.len());
+        }
+        // Dropping the set stops the downloads still going
+        self.prune();
+    }
+
+    /// What to print to draw the image at most `cols` wide, ending in a
+    /// newline, or None to show just the link
+    pub(crate) fn render(&self, url: &str, cols: usize) -> Option<String> {
+        let bytes = std::fs::read(self.path(url)).ok()?;
+        let cols = cols.clamp(1, MAX_COLS);
+        if self.protocol == Protocol::Iterm {
+            // iTerm decodes and scales the image itself
+            return Some(format!(
+                "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07\n",
+                bytes.len(),
+                cols,
+                MAX_ROWS,
+                base64::encode(&bytes)
+            ));
+        }
+        let image = decode(&bytes)?;
+        let drawn = match self.protocol {
+            Protocol::Blocks => blocks(
+                &image
+                    .thumbnail(cols as u32, (MAX_ROWS * 2) as u32)
+                    .to_rgb8(),
+            ),
+            protocol => {
+                let (cell_width, cell_height) = cell_pixels();
+                let image = image
+                    .thumbnail((cols * cell_width) as u32, (MAX_ROWS * cell_height) as u32)
+                    .to_rgb8();
+                if protocol == Protocol::Kitty {
+                    kitty(&image)
+                } else {
+                    sixel(&image)
+                }
+            }
+        };
+        Some(drawn + "\n")
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}", fnv1a(url)))
+    }
+
+    // Written whole and then moved into place, so another pane never reads
+    // half an image
+    fn store(&self, path: &PathBuf, bytes: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let tmp = path.with_extension(std::process::id().to_string());
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn prune(&self) {
+        let mut files: Vec<(std::time::SystemTime, PathBuf)> = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    Some((entry.metadata().ok()?.modified().ok()?, entry.path()))
+                })
+                .collect(),
+            Err(_) => return,
+        };
+        if files.len() <= MAX_CACHED {
+            return;
+        }
+        files.sort();
+        for (_, path) in &files[..files.len() - MAX_CACHED] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// Read a chunk at a time, since not every host says how big the image is
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    if response.content_length().unwrap_or(0) as usize > MAX_BYTES {
+        return Err(anyhow!("Too big"));
+    }
+    let mut bytes = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() > MAX_BYTES {
+            return Err(anyhow!("Too big"));
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode(bytes: &[u8]) -> Option<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_PIXELS_ACROSS);
+    limits.max_image_height = Some(MAX_PIXELS_ACROSS);
+    limits.max_alloc = Some(MAX_DECODED_BYTES);
+    let mut reader = Reader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?;
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+// Cache file names have to stay the same from one build to the next, which
+// std's hasher doesn't promise
+fn fnv1a(url: &str) -> u64 {
+    url.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn cell_pixels() -> (usize, usize) {
+    match (termion::terminal_size_pixels(), termion::terminal_size()) {
+        (Ok((px_width, px_height)), Ok((cols, rows))) if px_width > 0 && cols > 0 && rows > 0 => (
+            (px_width / cols).max(1) as usize,
+            (px_height / rows).max(1) as usize,
+        ),
+        _ => CELL_PIXELS,
+    }
+}
+
+// Two pixels per cell, the top one as the foreground of ▀ and the bottom
+// one as its background
+fn blocks(image: &RgbImage) -> String {
+    let truecolor = std::env::var("COLORTERM")
+        .map(|c| c.contains("truecolor") || c.contains("24bit"))
+        .unwrap_or(false);
+    let mut lines: Vec<String> = vec![];
+    for y in (0..image.height()).step_by(2) {
+        let mut line = String::new();
+        for x in 0..image.width() {
+            let top = image.get_pixel(x, y).0;
+            let bottom = if y + 1 < image.height() {
+                image.get_pixel(x, y + 1).0
+            } else {
+                [0, 0, 0]
+            };
+            if truecolor {
+                line.push_str(&format!(
+                    "{}{}▀",
+                    color::Fg(color::Rgb(top[0], top[1], top[2])),
+                    color::Bg(color::Rgb(bottom[0], bottom[1], bottom[2]))
+                ));
+            } else {
+                line.push_str(&format!(
+                    "{}{}▀",
+                    color::Fg(cube(top)),
+                    color::Bg(cube(bottom))
+                ));
+            }
+        }
+        line.push_str(termion::style::Reset.as_ref());
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+// The nearest of the 216 colors every 256-color terminal has
+fn cube(rgb: [u8; 3]) -> color::AnsiValue {
+    let level = |v: u8| ((v as u16 * 5 + 127) / 255) as u8;
+    color::AnsiValue::rgb(level(rgb[0]), level(rgb[1]), level(rgb[2]))
+}
+
+// Raw RGB sent in chunks, as the kitty graphics protocol wants. q=2 keeps
+// the terminal from answering on stdin, where the stream reads keys.
+fn kitty(image: &RgbImage) -> String {
+    let data = base64::encode(image.as_raw());
+    let chunks: Vec<&str> = data
+        .as_bytes()
+        .chunks(4096)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or(""))
+        .collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,q=2,f=24,s={},v={},m={};",
+                image.width(),
+                image.height(),
+                more
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(chunk);
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+// Sixel with the same 216 color cube as the palette, which is plenty for a
+// preview and needs no quantizing pass
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = (image.width(), image.height());
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for i in 0..216 {
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            i / 36 * 20,
+            i / 6 % 6 * 20,
+            i % 6 * 20
+        ));
+    }
+    for band in (0..height).step_by(6) {
+        // For every color in this band of six rows, which of those rows it
+        // fills in each column
+        let mut colors: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+        for x in 0..width {
+            for dy in 0..(height - band).min(6) {
+                let color = cube(image.get_pixel(x, band + dy).0).0 - 16;
+                colors
+                    .entry(color)
+                    .or_insert_with(|| vec![0; width as usize])[x as usize] |= 1 << dy;
+            }
+        }
+        for (color, bits) in colors {
+            out.push_str(&format!("#{}", color));
+            let mut x = 0;
+            while x < bits.len() {
+                let run = bits[x..].iter().take_while(|b| **b == bits[x]).count();
+                let c = (63 + bits[x]) as char;
+                if run > 3 {
+                    out.push_str(&format!("!{}{}", run, c));
+                } else {
+                    for _ in 0..run {
+                        out.push(c);
+                    }
+                }
+                x += run;
+            }
+            // Back to the start of the band for the next color
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_names_are_stable() {
+        // The published FNV-1a test vectors
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a("foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[tokio::test]
+    async fn stops_reading_past_the_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let chunk = vec![0u8; 1024 * 1024];
+        let _big = server
+            .mock("GET", "/big.jpg")
+            .with_chunked_body(move |w| {
+                for _ in 0..8 {
+                    w.write_all(&chunk)?;
+                }
+                Ok(())
+            })
+            .create_async()
+            .await;
+        let _small = server
+            .mock("GET", "/small.jpg")
+            .with_body("jpeg")
+            .create_async()
+            .await;
+
+        let client = reqwest::Client::new();
+        let err = download(&client, &format!("{}/big.jpg", server.url()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Too big");
+        let bytes = download(&client, &format!("{}/small.jpg", server.url()))
+            .await
+            .unwrap();
+        assert_eq!(bytes, b"jpeg");
+    }
+}
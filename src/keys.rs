@@ -0,0 +1,322 @@
+use anyhow::Result;
+use std::io::Stdout;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Duration, Instant};
+
+use crate::backend::Post;
+use crate::hints::{self, Hints};
+use crate::html;
+use crate::ui::UI;
+
+// Single key controls while streaming. The terminal is put in raw mode so
+// keys arrive without Enter and aren't echoed into the posts.
+
+const HELP: &str =
+    "q quit  r refresh now  p pause/resume  ? help  type a link's label, then Enter to open or Space to preview";
+// Give up on a preview after this long
+const PREVIEW_TIMEOUT_SECS: u64 = 10;
+
+enum Action {
+    Quit,
+    Refresh,
+    Pause,
+    Help,
+    Letter(char),
+    Open,
+    Preview,
+    Backspace,
+    Cancel,
+}
+
+/// What opens links when the config doesn't say
+pub(crate) fn default_opener() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    }
+}
+
+/// Keys are only read when both stdin and stdout are the terminal, so piped
+/// output is left alone
+pub(crate) fn interactive() -> bool {
+    termion::is_tty(&std::io::stdin()) && termion::is_tty(&std::io::stdout())
+}
+
+pub(crate) struct Controls<'a> {
+    ui: &'a UI,
+    keys: Option<mpsc::UnboundedReceiver<Action>>,
+    // Restores the terminal when dropped
+    _raw: Option<RawTerminal<Stdout>>,
+    paused: bool,
+    // Posts fetched while paused, oldest first
+    held: Vec<Post>,
+    hints: Option<Arc<Hints>>,
+    // The command links are opened with, split into words
+    opener: Vec<String>,
+    // The label typed so far
+    typed: String,
+}
+
+impl<'a> Controls<'a> {
+    pub(crate) fn new(ui: &'a UI, hints: Option<Arc<Hints>>, opener: &str) -> Controls<'a> {
+        let raw = if interactive() {
+            std::io::stdout().into_raw_mode().ok()
+        } else {
+            None
+        };
+        let keys = raw.as_ref().map(|_| {
+            ui.set_raw(true);
+            let (tx, rx) = mpsc::unbounded_channel();
+            // Reading stdin blocks, so it gets its own thread
+            std::thread::spawn(move || {
+                for key in std::io::stdin().keys() {
+                    let action = match key {
+                        Ok(Key::Char('q')) | Ok(Key::Ctrl('c')) => Action::Quit,
+                        Ok(Key::Char('r')) => Action::Refresh,
+                        Ok(Key::Char('p')) => Action::Pause,
+                        Ok(Key::Char('?')) => Action::Help,
+                        Ok(Key::Char('\n')) => Action::Open,
+                        Ok(Key::Char(' ')) => Action::Preview,
+                        Ok(Key::Char(c)) if hints::is_label_char(c) => Action::Letter(c),
+                        Ok(Key::Backspace) => Action::Backspace,
+                        Ok(Key::Esc) => Action::Cancel,
+                        Ok(_) => continue,
+                        Err(_) => break,
+                    };
+                    if tx.send(action).is_err() {
+                        break;
+                    }
+                }
+            });
+            rx
+        });
+        Controls {
+            ui,
+            keys,
+            _raw: raw,
+            paused: false,
+            held: vec![],
+            hints,
+            opener: opener.split_whitespace().map(str::to_string).collect(),
+            typed: String::new(),
+        }
+    }
+
+    /// Prints the posts (newest first), or holds on to them while paused.
+    /// Either way the status line is brought up to date.
+    pub(crate) async fn show(&mut self, posts: &[Post]) {
+        if self.paused {
+            self.held.extend(posts.iter().rev().cloned());
+            self.show_paused();
+        } else {
+            self.ui.clear_status();
+            let posts: Vec<&Post> = posts.iter().rev().collect();
+            self.ui.print_tweets(&posts).await;
+        }
+    }
+
+    /// Waits this long, or less if a key asks to refresh. False means quit.
+    pub(crate) async fn wait(&mut self, wait: Duration) -> bool {
+        let deadline = Instant::now() + wait;
+        loop {
+            let action = match self.keys {
+                Some(ref mut keys) => tokio::select! {
+                    _ = sleep_until(deadline) => return true,
+                    action = keys.recv() => action,
+                },
+                None => {
+                    sleep_until(deadline).await;
+                    return true;
+                }
+            };
+            match action {
+                // No more keys to read, so just wait it out
+                None => self.keys = None,
+                Some(Action::Quit) => return false,
+                Some(Action::Refresh) => return true,
+                Some(Action::Pause) => self.toggle_pause().await,
+                Some(Action::Help) => self.ui.print_status(HELP),
+                Some(Action::Letter(c)) => self.type_label(c),
+                Some(Action::Backspace) => {
+                    self.typed.pop();
+                    self.show_typed();
+                }
+                Some(Action::Cancel) => {
+                    self.typed.clear();
+                    self.ui.clear_status();
+                }
+                Some(Action::Open) => {
+                    if let Some(url) = self.take_url() {
+                        match open(&self.opener, &url) {
+                            Ok(()) => self.ui.print_status(&format!("Opened {}", url)),
+                            Err(err) => self
+                                .ui
+                                .print_status(&format!("Couldn't open {}: {}", url, err)),
+                        }
+                    }
+                }
+                Some(Action::Preview) => {
+                    if let Some(url) = self.take_url() {
+                        self.ui.print_status(&format!("Fetching {}", url));
+                        let note = match preview(&url).await {
+                            Ok(preview) => preview,
+                            Err(err) => format!("Couldn't preview {}: {}", url, err),
+                        };
+                        self.ui.clear_status();
+                        self.ui.print_note(&note);
+                    }
+                }
+            }
+        }
+    }
+
+    fn type_label(&mut self, c: char) {
+        let hints = match self.hints {
+            Some(ref hints) => hints,
+            None => return,
+        };
+        self.typed.push(c);
+        if !hints.is_prefix(&self.typed) {
+            self.ui
+                .print_status(&format!("No link labeled {}", &self.typed));
+            self.typed.clear();
+            return;
+        }
+        self.show_typed();
+    }
+
+    fn show_typed(&self) {
+        if self.typed.is_empty() {
+            self.ui.clear_status();
+        } else {
+            self.ui.print_status(&format!(
+                "[{}] Enter opens, Space previews, Esc cancels",
+                &self.typed
+            ));
+        }
+    }
+
+    // The link for the label typed so far, which starts over either way
+    fn take_url(&mut self) -> Option<String> {
+        let typed = std::mem::take(&mut self.typed);
+        let url = self.hints.as_ref().and_then(|hints| hints.url(&typed));
+        if url.is_none() && !typed.is_empty() {
+            self.ui.print_status(&format!("No link labeled {}", typed));
+        }
+        url
+    }
+
+    async fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if self.paused {
+            self.show_paused();
+        } else {
+            self.ui.clear_status();
+            let held: Vec<&Post> = self.held.iter().collect();
+            self.ui.print_tweets(&held).await;
+            self.held.clear();
+        }
+    }
+
+    fn show_paused(&self) {
+        self.ui.print_status(&match self.held.len() {
+            0 => "Paused, p to resume".to_string(),
+            1 => "Paused with 1 new post, p to resume".to_string(),
+            held => format!("Paused with {} new posts, p to resume", held),
+        });
+    }
+}
+
+// Runs the opener command with the link after its own arguments
+fn open(opener: &[String], url: &str) -> Result<()> {
+    let (program, args) = opener
+        .split_first()
+        .ok_or(anyhow::anyhow!("No opener command"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Reap it whenever it finishes
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// The page's title and description, or its type when it isn't a web page
+async fn preview(url: &str) -> Result<String> {
+    let response = reqwest::Client::new()
+        .get(url)
+        .timeout(Duration::from_secs(PREVIEW_TIMEOUT_SECS))
+        .send()
+        .await?
+        .error_for_status()?;
+    let kind = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|kind| kind.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let mut lines = vec![format!("➜ {}", url)];
+    if kind.contains("html") {
+        let (title, description) = html::summary(&response.text().await?);
+        lines.push(format!("  {}", title.as_deref().unwrap_or("(no title)")));
+        if let Some(description) = description {
+            lines.push(format!("  {}", description));
+        }
+    } else {
+        lines.push(format!("  {}", kind));
+    }
+    Ok(lines.join("\n"))
+}
+
+impl Drop for Controls<'_> {
+    fn drop(&mut self) {
+        self.ui.clear_status();
+        self.ui.set_raw(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_with_the_opener_and_its_arguments() {
+        let file = std::env::temp_dir().join(format!("babble_opened_{}", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        // The link comes after the opener's own arguments, as $1 here
+        let opener: Vec<String> = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("printf %s \"$1\" > {}", file.display()),
+            "sh".to_string(),
+        ];
+        open(&opener, "https://example.com/a?b=c&d").unwrap();
+
+        let mut opened = String::new();
+        for _ in 0..100 {
+            opened = std::fs::read_to_string(&file).unwrap_or_default();
+            if !opened.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(opened, "https://example.com/a?b=c&d");
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn opener_must_run() {
+        assert!(open(&[], "https://example.com").is_err());
+        let missing = vec!["babble-no-such-opener".to_string()];
+        assert!(open(&missing, "https://example.com").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
+use std::sync::Mutex;
+
+// Log lines go to the terminal as they come, except while the tui has the
+// screen, where they'd draw over it. Then they're held back, the newest is
+// shown on the tui's bottom row and the lot are printed once it's done.
+
+// The lines held back so far, or None while they go straight out
+static HELD: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+struct Logger {
+    term: Box<TermLogger>,
+}
+
+pub(crate) fn init(level: LevelFilter) -> Result<()> {
+    let term = TermLogger::new(
+        level,
+        Config::default(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    );
+    log::set_boxed_logger(Box::new(Logger { term }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.term.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match *held() {
+            Some(ref mut lines) => lines.push(format!("{}: {}", record.level(), record.args())),
+            None => self.term.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.term.flush()
+    }
+}
+
+fn held() -> std::sync::MutexGuard<'static, Option<Vec<String>>> {
+    HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Holds log lines back until the guard is dropped, when they go to stderr
+pub(crate) fn hold() -> Held {
+    *held() = Some(vec![]);
+    Held
+}
+
+/// The newest line held back
+pub(crate) fn newest() -> Option<String> {
+    held().as_ref().and_then(|lines| lines.last().cloned())
+}
+
+pub(crate) struct Held;
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        for line in held().take().unwrap_or_default() {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_lines_back_while_asked_to() {
+        let logger = Logger {
+            term: TermLogger::new(
+                LevelFilter::Warn,
+                Config::default(),
+                TerminalMode::Stderr,
+                ColorChoice::Never,
+            ),
+        };
+        let warn = |message| {
+            logger.log(
+                &Record::builder()
+                    .level(log::Level::Warn)
+                    .args(format_args!("{}", message))
+                    .build(),
+            )
+        };
+
+        let held = hold();
+        warn("first");
+        warn("second");
+        assert_eq!(newest().as_deref(), Some("WARN: second"));
+        drop(held);
+
+        warn("after");
+        assert_eq!(newest(), None);
+    }
+}
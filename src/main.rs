@@ -5,27 +5,50 @@ extern crate egg_mode;
 extern crate serde;
 extern crate simplelog;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use backend::{Backend, BackendKind, Feed, Query};
+use chrono::{DateTime, Local, Utc};
 use clap_verbosity_flag::Verbosity;
 use confy::ConfyError;
-use egg_mode::tweet::Timeline;
+use log::LevelFilter;
 use serde::{Deserialize, Serialize};
-use simplelog::*;
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
 use structopt::clap::crate_version;
 use structopt::StructOpt;
 use text_io::read;
+use tokio::time::Duration;
 
+mod backend;
+mod bluesky;
+mod cursor;
+mod format;
+mod hints;
+mod html;
+mod images;
+mod keys;
+mod logging;
+mod mastodon;
+mod replay;
+mod rss;
+mod text;
+mod theme;
+mod timeline;
+mod timestamp;
+mod tui;
 mod twitter;
 mod ui;
 
 // babble-cli
 
 const APP_NAME: &'static str = "babble";
+// How many posts to show when neither --count nor the config says
+const DEFAULT_COUNT: usize = 15;
 
 #[derive(Debug, StructOpt)]
-#[structopt(name = APP_NAME, version = crate_version!(), about = "Fetch Twitter data", rename_all = "kebab-case")]
+#[structopt(name = APP_NAME, version = crate_version!(), about = "Fetch social media timelines", rename_all = "kebab-case")]
 struct Cli {
     /// The config file to use
     #[structopt(short, long, parse(from_os_str))]
@@ -35,13 +58,77 @@ struct Cli {
     #[structopt(flatten)]
     verbose: Verbosity,
 
+    /// Where to read from (twitter, mastodon, bluesky or rss), defaults to the config setting
+    #[structopt(short, long)]
+    backend: Option<BackendKind>,
+
+    /// Play back tweets saved with --record instead of fetching them
+    #[structopt(long, parse(from_os_str))]
+    replay: Option<PathBuf>,
+
+    /// With --replay and --stream, release a tweet every this many seconds
+    #[structopt(long)]
+    pace: Option<u64>,
+
+    /// Save every fetched page of tweets to this file for use with --replay
+    #[structopt(long, parse(from_os_str), conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
     /// Stream the feed
     #[structopt(short, long)]
     stream: bool,
 
-    /// Output in markdown format
+    /// How many posts to show, paging back through the feed for more than fit on one page
+    #[structopt(long)]
+    count: Option<usize>,
+
+    /// Seconds between polls when streaming, or auto to poll faster when busy and slower when quiet
+    #[structopt(long, conflicts_with = "pace")]
+    interval: Option<timeline::Interval>,
+
+    /// When streaming, show at most this many of the posts made since the last run
+    #[structopt(long, default_value = "100")]
+    catch_up: usize,
+
+    /// When streaming, start from the latest posts instead of where the last run left off
+    #[structopt(long)]
+    fresh: bool,
+
+    /// Only show posts from this time on, like "yesterday", "last monday" or 2021-10-15
+    #[structopt(long, parse(try_from_str = parse_time))]
+    since: Option<DateTime<Utc>>,
+
+    /// Only show posts from before this time
+    #[structopt(long, parse(try_from_str = parse_time), conflicts_with = "stream")]
+    until: Option<DateTime<Utc>>,
+
+    /// Output format: terminal, markdown, json, ndjson, html, html-dark, org or plain
+    #[structopt(short, long, default_value = "terminal")]
+    format: String,
+
+    /// Wrap post text to this many columns instead of the terminal's width, 0 to not wrap
     #[structopt(short, long)]
-    markdown: bool,
+    width: Option<usize>,
+
+    /// How to show post times: a strftime string like "%H:%M" or relative
+    #[structopt(long)]
+    time_format: Option<String>,
+
+    /// Timezone for post times, like UTC or America/Denver, instead of local time
+    #[structopt(long)]
+    timezone: Option<String>,
+
+    /// When to color the terminal output: never, auto or always
+    #[structopt(long, default_value = "auto")]
+    color: theme::ColorWhen,
+
+    /// Draw post images in the terminal: never, auto, kitty, iterm, sixel or blocks
+    #[structopt(long)]
+    images: Option<images::ImageMode>,
+
+    /// Render each post with this template file instead of a --format
+    #[structopt(short, long, parse(from_os_str))]
+    template: Option<PathBuf>,
 
     #[structopt(subcommand)]
     cmd: Option<Command>,
@@ -59,12 +146,68 @@ enum Command {
     },
     /// My recent tweets and likes
     Me,
+    /// Full screen columns for home and any lists, side by side
+    Tui {
+        /// Add a column for this list, can be given more than once
+        #[structopt(short = "n", long = "list")]
+        lists: Vec<String>,
+        /// Add a column for my tweets and likes
+        #[structopt(long)]
+        me: bool,
+    },
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
 struct MyConfig {
+    backend: BackendKind,
     consumer_key: String,
     consumer_secret: String,
+    mastodon_instance: String,
+    mastodon_client_id: String,
+    mastodon_client_secret: String,
+    /// Template file to use when --template isn't given
+    template: Option<PathBuf>,
+    /// Terminal color theme: default, light or mono
+    theme: Option<String>,
+    /// Used when --count isn't given
+    count: Option<usize>,
+    /// Used when --interval isn't given
+    interval: Option<String>,
+    /// Command to open links with while streaming, like firefox or open -a Safari
+    opener: Option<String>,
+    /// Used when --images isn't given
+    images: Option<String>,
+    /// Used when --time-format isn't given
+    time_format: Option<String>,
+    /// Used when --timezone isn't given
+    timezone: Option<String>,
+    /// Defaults to https://bsky.social
+    bluesky_pds: String,
+    /// Colors to use instead of the theme's for user, time, url, meta and hashtag
+    colors: theme::Colors,
+    /// Custom feed names for `list -n` mapped to their at:// URIs
+    bluesky_feeds: BTreeMap<String, String>,
+    /// RSS/Atom feed groups for `list -n`, each a list of URLs or file paths
+    feeds: BTreeMap<String, Vec<String>>,
+}
+
+impl MyConfig {
+    fn has_credentials(&self, backend: BackendKind) -> bool {
+        match backend {
+            BackendKind::Twitter => {
+                !self.consumer_key.is_empty() && !self.consumer_secret.is_empty()
+            }
+            BackendKind::Mastodon => {
+                !self.mastodon_instance.is_empty()
+                    && !self.mastodon_client_id.is_empty()
+                    && !self.mastodon_client_secret.is_empty()
+            }
+            // Bluesky logins are prompted for when there's no saved session
+            BackendKind::Bluesky => true,
+            BackendKind::Rss => !self.feeds.is_empty(),
+        }
+    }
 }
 
 #[tokio::main]
@@ -72,7 +215,7 @@ async fn main() {
     ::std::process::exit(match run().await {
         Ok(_) => 0,
         Err(err) => {
-            println!("Error: {}", err);
+            eprintln!("Error: {}", err);
             1
         }
     });
@@ -83,65 +226,233 @@ async fn run() -> Result<()> {
     let args = Cli::from_args();
     setup_logging(&args.verbose).expect("Failed to initialize logging");
     debug!("Got args {:?}", args);
-    let cfg: MyConfig = get_config_from_file(&args.config_file)
-        .and_then(|cfg: MyConfig| {
-            // If reading the config didn't throw an error, but produced a default
-            // config with no values, then prompt the user.
-            if cfg.consumer_key == "" || cfg.consumer_secret == "" {
-                Ok(get_config_from_user(&args.config_file)?)
-            } else {
-                Ok(cfg)
-            }
-        })
-        // And if some error happened on reading, try to prompt the user and write.
-        .or_else(|_| get_config_from_user(&args.config_file))?;
+    // Like --stream, the tui keeps going, so a range can't have an end
+    if args.until.is_some() && matches!(args.cmd, Some(Command::Tui { .. })) {
+        return Err(anyhow!("--until can't be used with tui"));
+    }
 
-    debug!("Got config {:?}", cfg);
+    // Settings are read without prompting so that --replay needs no credentials.
+    // A file that doesn't parse is never prompted over, as saving the answers
+    // would wipe out everything else in it.
+    let cfg: Option<MyConfig> = match get_config_from_file(&args.config_file) {
+        Ok(cfg) => Some(cfg),
+        Err(err) if matches!(err.downcast_ref(), Some(ConfyError::BadTomlData(_))) => {
+            return Err(anyhow!("Fix or remove the config file. {}", err));
+        }
+        Err(_) => None,
+    };
+
+    // Links get labels to open them by when streaming to a terminal
+    let hints = if args.stream && keys::interactive() {
+        Some(Arc::new(hints::Hints::default()))
+    } else {
+        None
+    };
+
+    // Only the terminal format draws images
+    let image_mode = match (args.images, &cfg) {
+        (Some(mode), _) => mode,
+        (None, Some(cfg)) => cfg
+            .images
+            .as_deref()
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or(images::ImageMode::Never),
+        (None, None) => images::ImageMode::Never,
+    };
+    let images = if args.format == "terminal" && args.template.is_none() {
+        images::Images::new(image_mode, theme::enabled(args.color)).map(Arc::new)
+    } else {
+        None
+    };
 
     // Init UI
-    let ui = ui::UI::new();
+    let options = format::Options {
+        width: args.width,
+        theme: load_theme(&args, cfg.as_ref())?,
+        time: timestamp::TimeFormat::new(
+            args.time_format
+                .as_deref()
+                .or_else(|| cfg.as_ref().and_then(|cfg| cfg.time_format.as_deref())),
+            args.timezone
+                .as_deref()
+                .or_else(|| cfg.as_ref().and_then(|cfg| cfg.timezone.as_deref())),
+        )?,
+        hints: hints.clone(),
+        images: images.clone(),
+    };
+    let formatter = match args
+        .template
+        .clone()
+        .or_else(|| cfg.as_ref().and_then(|cfg| cfg.template.clone()))
+    {
+        Some(template) => format::template(&template, &options)?,
+        // Back to back arrays aren't valid JSON, so streams print a post per line
+        None if args.stream && args.format == "json" => format::formatter("ndjson", &options)?,
+        None => format::formatter(&args.format, &options)?,
+    };
+    let ui = ui::UI::new(formatter, images);
 
-    // Initialize Twitter
-    if args.markdown {
-        println!("```\n");
-    }
-    let twitter = twitter::Twitter::init(&cfg).await?;
-    if args.markdown {
-        println!("```\n");
-    }
+    // --pace sets the interval when replaying
+    let interval = match (args.interval, &cfg) {
+        (Some(interval), _) => Some(interval),
+        (None, Some(cfg)) if args.pace.is_none() => {
+            cfg.interval.as_deref().map(str::parse).transpose()?
+        }
+        _ => None,
+    };
+    let query = Query {
+        limit: args
+            .count
+            .or_else(|| cfg.as_ref().and_then(|cfg| cfg.count))
+            .unwrap_or(DEFAULT_COUNT),
+        after: args.since,
+        before: args.until,
+        ..Default::default()
+    };
+
+    let kind = args
+        .backend
+        .or_else(|| cfg.as_ref().map(|cfg| cfg.backend))
+        .unwrap_or_default();
+    let opener = cfg
+        .as_ref()
+        .and_then(|cfg| cfg.opener.clone())
+        .unwrap_or_else(|| keys::default_opener().to_string());
 
-    let timeline: Timeline = match args.cmd {
-        None | Some(Command::Home) => twitter.home().await,
-        Some(Command::List { name }) => twitter.list(&name).await,
-        Some(Command::Me) => twitter.me().await,
-    }?;
+    // Initialize the backend
+    let backend: Box<dyn Backend> = match args.replay {
+        Some(ref path) => Box::new(replay::Replay::load(
+            path,
+            args.pace.map(Duration::from_secs),
+        )?),
+        None => init_backend(&args, cfg).await?,
+    };
+    ui.print_banner(backend.handle());
+
+    // Streams keep their place unless replaying, and a --since range says
+    // where to start instead
+    let keep_place = args.replay.is_none();
+    let catch_up = if args.fresh || args.since.is_some() {
+        None
+    } else {
+        Some(args.catch_up)
+    };
+    let stream_options = |feed: &Feed| timeline::Options {
+        interval,
+        cursor: if keep_place {
+            Some(cursor::Cursor::new(kind, backend.handle(), feed))
+        } else {
+            None
+        },
+        catch_up,
+        ..Default::default()
+    };
+
+    let feed: Feed = match args.cmd {
+        None | Some(Command::Home) => Feed::Home,
+        Some(Command::List { name }) => Feed::List(name),
+        Some(Command::Me) => Feed::Me,
+        Some(Command::Tui { lists, me }) => {
+            let mut feeds = vec![Feed::Home];
+            feeds.extend(lists.into_iter().map(Feed::List));
+            if me {
+                feeds.push(Feed::Me);
+            }
+            let columns = feeds
+                .into_iter()
+                .map(|feed| {
+                    let options = stream_options(&feed);
+                    (feed, options)
+                })
+                .collect();
+            return tui::run(Arc::from(backend), columns, query, options).await;
+        }
+    };
 
     if args.stream {
-        twitter
-            .timeline_stream(timeline, &ui, 15, args.markdown)
-            .await?;
+        let options = timeline::Options {
+            hints,
+            opener,
+            ..stream_options(&feed)
+        };
+        timeline::timeline_stream(&*backend, &feed, &ui, query, options).await?;
     } else {
-        twitter
-            .timeline_print(timeline, &ui, 15, args.markdown)
-            .await?;
+        timeline::timeline_print(&*backend, &feed, &ui, query).await?;
     }
 
     Ok(())
 }
+
+// Natural language or an RFC 3339 timestamp, in local time unless it says
+fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    chrono_english::parse_date_string(s, Local::now(), chrono_english::Dialect::Us)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| anyhow!("Can't make sense of the time {}: {}", s, err))
+}
+
+fn load_theme(args: &Cli, cfg: Option<&MyConfig>) -> Result<theme::Theme> {
+    if !theme::enabled(args.color) {
+        return Ok(theme::Theme::default());
+    }
+    let name = cfg
+        .and_then(|cfg| cfg.theme.as_deref())
+        .unwrap_or("default");
+    let colors = cfg.map(|cfg| cfg.colors.clone()).unwrap_or_default();
+    theme::Theme::load(name, &colors)
+}
+
+async fn init_backend(args: &Cli, cfg: Option<MyConfig>) -> Result<Box<dyn Backend>> {
+    let cfg: MyConfig = match cfg {
+        Some(cfg) => {
+            // If reading the config didn't throw an error, but produced a default
+            // config with no values for this backend, then prompt the user.
+            let backend = args.backend.unwrap_or(cfg.backend);
+            if !cfg.has_credentials(backend) {
+                get_config_from_user(&args.config_file, cfg, backend)?
+            } else {
+                cfg
+            }
+        }
+        // And if some error happened on reading, try to prompt the user and write.
+        None => get_config_from_user(
+            &args.config_file,
+            MyConfig::default(),
+            args.backend.unwrap_or_default(),
+        )?,
+    };
+
+    debug!("Got config {:?}", cfg);
+    let backend_kind = args.backend.unwrap_or(cfg.backend);
+    if args.record.is_some() && backend_kind != BackendKind::Twitter {
+        return Err(anyhow!("Only Twitter timelines can be recorded"));
+    }
+
+    Ok(match backend_kind {
+        BackendKind::Twitter => {
+            let twitter = twitter::Twitter::init(&cfg).await?;
+            match args.record {
+                Some(ref path) => Box::new(twitter.record_to(path.clone())),
+                None => Box::new(twitter),
+            }
+        }
+        BackendKind::Mastodon => Box::new(mastodon::Mastodon::init(&cfg).await?),
+        BackendKind::Bluesky => Box::new(bluesky::Bluesky::init(&cfg).await?),
+        BackendKind::Rss => Box::new(rss::Rss::init(&cfg).await?),
+    })
+}
+
 fn setup_logging(v: &Verbosity) -> Result<()> {
-    Ok(TermLogger::init(
-        match v.log_level().unwrap_or(log::Level::Error) {
-            log::Level::Trace => LevelFilter::Trace,
-            log::Level::Debug => LevelFilter::Debug,
-            log::Level::Info => LevelFilter::Info,
-            log::Level::Warn => LevelFilter::Warn,
-            log::Level::Error => LevelFilter::Error,
-        },
-        // LevelFilter::Info,
-        Config::default(),
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )?)
+    logging::init(match v.log_level().unwrap_or(log::Level::Error) {
+        log::Level::Trace => LevelFilter::Trace,
+        log::Level::Debug => LevelFilter::Debug,
+        log::Level::Info => LevelFilter::Info,
+        log::Level::Warn => LevelFilter::Warn,
+        log::Level::Error => LevelFilter::Error,
+    })
 }
 
 fn get_config_from_file(config_file: &Option<PathBuf>) -> Result<MyConfig> {
@@ -153,19 +464,31 @@ fn get_config_from_file(config_file: &Option<PathBuf>) -> Result<MyConfig> {
 
 fn get_config_from_user(
     config_file: &Option<PathBuf>,
+    mut cfg: MyConfig,
+    backend: BackendKind,
 ) -> core::result::Result<MyConfig, ConfyError> {
     // No preference file found so prompt the user
-    let mut stdo = std::io::stdout();
-    print!("Enter the Twitter consumer key: ");
-    let _ = stdo.flush();
-    let consumer_key: String = read!("{}\n");
-    print!("Enter the Twitter consumer secret: ");
-    let _ = stdo.flush();
-    let consumer_secret: String = read!("{}\n");
-    let cfg = MyConfig {
-        consumer_key,
-        consumer_secret,
-    };
+    match backend {
+        BackendKind::Twitter => {
+            cfg.consumer_key = prompt("Enter the Twitter consumer key: ");
+            cfg.consumer_secret = prompt("Enter the Twitter consumer secret: ");
+        }
+        BackendKind::Mastodon => {
+            cfg.mastodon_instance = prompt("Enter the Mastodon instance URL: ");
+            cfg.mastodon_client_id = prompt("Enter the Mastodon client key: ");
+            cfg.mastodon_client_secret = prompt("Enter the Mastodon client secret: ");
+        }
+        BackendKind::Bluesky => (),
+        BackendKind::Rss => {
+            let group = prompt("Enter a name for a group of feeds: ");
+            let sources =
+                prompt("Enter the feed URLs or files for the group, separated by spaces: ");
+            cfg.feeds.insert(
+                group,
+                sources.split_whitespace().map(|s| s.to_string()).collect(),
+            );
+        }
+    }
     // And then save to the preference file so we don't have to prompt again
     match config_file {
         Some(ref config_file) => confy::store_path(config_file, &cfg),
@@ -173,3 +496,11 @@ fn get_config_from_user(
     }
     .map(|_| cfg)
 }
+
+fn prompt(question: &str) -> String {
+    // Prompts go to stderr so they stay out of piped output
+    let mut stde = std::io::stderr();
+    eprint!("{}", question);
+    let _ = stde.flush();
+    read!("{}\n")
+}
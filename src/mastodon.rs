@@ -0,0 +1,583 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::{
+    backend::{
+        append_link, check_response, home_file, shift_entities, Author, Backend, Media, Post,
+        Query, RateLimit, ReplyTo, MAX_PAGES,
+    },
+    html, MyConfig,
+};
+
+const AUTH_TOKENS_FILE: &str = ".mastodon_cli_oauth";
+const OOB_REDIRECT: &str = "urn:ietf:wg:oauth:2.0:oob";
+// Mastodon refuses to return more than this many statuses per call
+const MAX_PAGE_SIZE: usize = 40;
+
+pub(crate) struct Mastodon {
+    client: reqwest::Client,
+    instance: String,
+    access_token: String,
+    account_id: String,
+    acct: String,
+    list_ids: Mutex<HashMap<String, String>>,
+    rate_limit: Mutex<Option<RateLimit>>,
+}
+
+impl Mastodon {
+    pub(crate) async fn init(cfg: &MyConfig) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let instance = cfg
+            .mastodon_instance
+            .trim()
+            .trim_end_matches('/')
+            .to_string();
+        if instance.is_empty() {
+            return Err(anyhow!("No Mastodon instance configured"));
+        }
+
+        let (access_token, account) = match fetch_login(&client, &instance).await {
+            Ok(login) => login,
+            Err(_) => login(&client, &instance, cfg).await?,
+        };
+
+        Ok(Mastodon {
+            client,
+            instance,
+            access_token,
+            account_id: account.id,
+            acct: account.acct,
+            list_ids: Mutex::new(HashMap::new()),
+            rate_limit: Mutex::new(None),
+        })
+    }
+
+    async fn get<T>(&self, path: &str, query: &[(&str, String)]) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let response = self
+            .client
+            .get(format!("{}{}", self.instance, path))
+            .bearer_auth(&self.access_token)
+            .query(query)
+            .send()
+            .await?;
+        Ok(check_response(response, &self.rate_limit)?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn list_id(&self, list_name: &str) -> Result<String> {
+        let key = list_name.to_lowercase();
+        if let Some(id) = self.list_ids.lock().unwrap().get(&key) {
+            return Ok(id.clone());
+        }
+        let lists: Vec<MastodonList> = self.get("/api/v1/lists", &[]).await?;
+        let list = lists
+            .into_iter()
+            .find(|l| l.title.to_lowercase() == key)
+            .ok_or(anyhow!("List not found"))?;
+        self.list_ids.lock().unwrap().insert(key, list.id.clone());
+        Ok(list.id)
+    }
+
+    async fn fetch_timeline(&self, path: &str, query: &Query) -> Result<Vec<Post>> {
+        let page_size = query.page_size(MAX_PAGE_SIZE);
+        let mut posts: Vec<Post> = vec![];
+        let mut max_id: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let mut params = vec![("limit", page_size.to_string())];
+            if let Some(ref since) = query.since {
+                params.push(("since_id", since.clone()));
+            }
+            if let Some(ref max_id) = max_id {
+                params.push(("max_id", max_id.clone()));
+            }
+            let statuses: Vec<Status> = self.get(path, &params).await?;
+            posts.extend(statuses.iter().map(|s| self.to_post(s)));
+            if !query.wants_more(&posts, statuses.len(), MAX_PAGE_SIZE) {
+                break;
+            }
+            max_id = statuses.last().map(|s| s.id.clone());
+        }
+        Ok(query.keep(posts))
+    }
+
+    fn to_post(&self, status: &Status) -> Post {
+        let (body, mut urls, mut tags) = html::to_text(&status.content);
+        let mut text = if status.spoiler_text.is_empty() {
+            "".to_string()
+        } else {
+            format!("CW: {}\n", &status.spoiler_text)
+        };
+        shift_entities(&mut urls, &mut tags, text.len());
+        text.push_str(&body);
+        // Attachments aren't part of the content, so tack their links onto
+        // the end where the renderers will pick them up
+        let media: Vec<Media> = status
+            .media_attachments
+            .iter()
+            .map(|m| Media {
+                range: Some(append_link(&mut text, &m.url)),
+                url: m.url.clone(),
+                display_url: m.url.clone(),
+                // Like Twitter, videos and gifs are represented by their thumbnail
+                media_url: if m.kind == "image" {
+                    m.url.clone()
+                } else {
+                    m.preview_url.clone().unwrap_or_else(|| m.url.clone())
+                },
+            })
+            .collect();
+
+        let reply_to = status.in_reply_to_id.as_ref().map(|id| {
+            let handle = status
+                .mentions
+                .iter()
+                .find(|m| Some(&m.id) == status.in_reply_to_account_id.as_ref())
+                .map(|m| m.acct.clone())
+                .unwrap_or_else(|| status.account.acct.clone());
+            ReplyTo {
+                url: format!("{}/@{}/{}", self.instance, &handle, id),
+                handle,
+            }
+        });
+
+        Post {
+            id: status.id.clone(),
+            author: Author {
+                handle: status.account.acct.clone(),
+                name: if status.account.display_name.is_empty() {
+                    status.account.username.clone()
+                } else {
+                    status.account.display_name.clone()
+                },
+                url: status.account.url.clone(),
+            },
+            created_at: status.created_at,
+            text,
+            urls,
+            media,
+            tags,
+            repost_count: status.reblogs_count,
+            like_count: status.favourites_count,
+            source: status.application.as_ref().map(|a| a.name.clone()),
+            place: None,
+            reply_to,
+            reposted: status.reblog.as_ref().map(|rb| Box::new(self.to_post(rb))),
+            quoted: status
+                .quote
+                .as_ref()
+                .and_then(|q| q.quoted_status.as_ref())
+                .map(|qt| Box::new(self.to_post(qt))),
+            activity: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for Mastodon {
+    fn handle(&self) -> &str {
+        &self.acct
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    async fn me(&self, query: &Query) -> Result<Vec<Post>> {
+        self.fetch_timeline(
+            &format!("/api/v1/accounts/{}/statuses", &self.account_id),
+            query,
+        )
+        .await
+    }
+
+    // Favourites page by when they were favourited, which the ids in a
+    // query know nothing about, so this is only ever the latest page
+    async fn likes(&self, query: &Query) -> Result<Vec<Post>> {
+        let params = vec![("limit", query.limit.min(MAX_PAGE_SIZE).to_string())];
+        let statuses: Vec<Status> = self.get("/api/v1/favourites", &params).await?;
+        Ok(statuses.iter().map(|s| self.to_post(s)).collect())
+    }
+
+    async fn list(&self, list_name: &str, query: &Query) -> Result<Vec<Post>> {
+        let list_id = self.list_id(list_name).await?;
+        self.fetch_timeline(&format!("/api/v1/timelines/list/{}", list_id), query)
+            .await
+    }
+
+    async fn home(&self, query: &Query) -> Result<Vec<Post>> {
+        self.fetch_timeline("/api/v1/timelines/home", query).await
+    }
+}
+
+// The subset of the Mastodon API entities that babble renders
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    id: String,
+    created_at: DateTime<Utc>,
+    content: String,
+    #[serde(default)]
+    spoiler_text: String,
+    account: Account,
+    reblog: Option<Box<Status>>,
+    in_reply_to_id: Option<String>,
+    in_reply_to_account_id: Option<String>,
+    reblogs_count: u64,
+    favourites_count: u64,
+    application: Option<Application>,
+    #[serde(default)]
+    mentions: Vec<Mention>,
+    #[serde(default)]
+    media_attachments: Vec<Attachment>,
+    // Only sent by servers that support quote posts
+    #[serde(default)]
+    quote: Option<Quote>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    id: String,
+    username: String,
+    acct: String,
+    display_name: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Mention {
+    id: String,
+    acct: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Attachment {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    preview_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Application {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Quote {
+    quoted_status: Option<Box<Status>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MastodonList {
+    id: String,
+    title: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedLogin {
+    instance: String,
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+async fn verify_credentials(
+    client: &reqwest::Client,
+    instance: &str,
+    access_token: &str,
+) -> Result<Account> {
+    Ok(client
+        .get(format!("{}/api/v1/accounts/verify_credentials", instance))
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn fetch_login(client: &reqwest::Client, instance: &str) -> Result<(String, Account)> {
+    let file = std::fs::File::open(home_file(AUTH_TOKENS_FILE))?;
+    let reader = std::io::BufReader::new(file);
+    let saved: SavedLogin = serde_json::from_reader(reader)?;
+    if saved.instance != instance {
+        return Err(anyhow!("Saved login is for {}", &saved.instance));
+    }
+    let account = verify_credentials(client, instance, &saved.access_token).await?;
+    Ok((saved.access_token, account))
+}
+
+async fn save_login(instance: &str, access_token: &str) -> Result<()> {
+    use std::fs::OpenOptions;
+    let file = &OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(home_file(AUTH_TOKENS_FILE))?;
+    serde_json::to_writer(
+        file,
+        &SavedLogin {
+            instance: instance.to_string(),
+            access_token: access_token.to_string(),
+        },
+    )?;
+    Ok(())
+}
+
+// Out of band OAuth: the user approves the app in their browser and pastes
+// the code the instance shows them back here
+async fn login(
+    client: &reqwest::Client,
+    instance: &str,
+    cfg: &MyConfig,
+) -> Result<(String, Account)> {
+    let client_id = cfg.mastodon_client_id.trim();
+    let client_secret = cfg.mastodon_client_secret.trim();
+
+    let auth_url = reqwest::Url::parse_with_params(
+        &format!("{}/oauth/authorize", instance),
+        &[
+            ("client_id", client_id),
+            ("redirect_uri", OOB_REDIRECT),
+            ("response_type", "code"),
+            ("scope", "read"),
+        ],
+    )?;
+    eprintln!(
+        "Please open {} and grant access, then paste the code back here:",
+        &auth_url
+    );
+
+    let mut code = String::new();
+    std::io::stdin().read_line(&mut code)?;
+
+    let token: TokenResponse = client
+        .post(format!("{}/oauth/token", instance))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code.trim()),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+            ("redirect_uri", OOB_REDIRECT),
+            ("scope", "read"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let account = verify_credentials(client, instance, &token.access_token).await?;
+    save_login(instance, &token.access_token).await?;
+
+    Ok((token.access_token, account))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use mockito::{Matcher, Mock, Server, ServerGuard};
+    use serde_json::json;
+
+    fn mastodon(server: &ServerGuard) -> Mastodon {
+        Mastodon {
+            client: reqwest::Client::new(),
+            instance: server.url(),
+            access_token: "token".to_string(),
+            account_id: "1".to_string(),
+            acct: "alice".to_string(),
+            list_ids: Mutex::new(HashMap::new()),
+            rate_limit: Mutex::new(None),
+        }
+    }
+
+    // A status a minute newer for every id
+    fn status(id: u64) -> serde_json::Value {
+        json!({
+            "id": id.to_string(),
+            "created_at": Utc.timestamp_opt(1_600_000_000 + id as i64 * 60, 0).unwrap(),
+            "content": format!("<p>Post {}</p>", id),
+            "account": {
+                "id": "2",
+                "username": "bob",
+                "acct": "bob@example.com",
+                "display_name": "Bob",
+                "url": "https://example.com/@bob",
+            },
+            "reblog": null,
+            "in_reply_to_id": null,
+            "in_reply_to_account_id": null,
+            "reblogs_count": 0,
+            "favourites_count": 0,
+            "application": null,
+        })
+    }
+
+    // A page of statuses, newest first, counting down from `newest`
+    fn page(newest: u64, count: u64) -> String {
+        let statuses: Vec<serde_json::Value> = (0..count).map(|i| status(newest - i)).collect();
+        serde_json::to_string(&statuses).unwrap()
+    }
+
+    async fn mock(server: &mut ServerGuard, path: &str, query: &str, body: String) -> Mock {
+        server
+            .mock("GET", path)
+            .match_query(Matcher::Exact(query.to_string()))
+            .match_header("authorization", "Bearer token")
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create_async()
+            .await
+    }
+
+    fn ids(posts: &[Post]) -> Vec<u64> {
+        posts.iter().map(|p| p.id.parse().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn home_pages_back_with_max_id() {
+        let mut server = Server::new_async().await;
+        let path = "/api/v1/timelines/home";
+        let first = mock(&mut server, path, "limit=40", page(100, 40)).await;
+        let second = mock(&mut server, path, "limit=40&max_id=61", page(60, 40)).await;
+
+        let query = Query {
+            limit: 50,
+            ..Default::default()
+        };
+        let posts = mastodon(&server).home(&query).await.unwrap();
+
+        first.assert_async().await;
+        second.assert_async().await;
+        assert_eq!(ids(&posts), (51..=100).rev().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn home_asks_for_posts_since_id() {
+        let mut server = Server::new_async().await;
+        let path = "/api/v1/timelines/home";
+        let only = mock(&mut server, path, "limit=5&since_id=90", page(95, 5)).await;
+
+        let query = Query {
+            limit: 5,
+            since: Some("90".to_string()),
+            ..Default::default()
+        };
+        let posts = mastodon(&server).home(&query).await.unwrap();
+
+        only.assert_async().await;
+        assert_eq!(ids(&posts), vec![95, 94, 93, 92, 91]);
+    }
+
+    #[tokio::test]
+    async fn list_looks_up_its_id_once() {
+        let mut server = Server::new_async().await;
+        let lists = server
+            .mock("GET", "/api/v1/lists")
+            .with_header("content-type", "application/json")
+            .with_body(r#"[{"id": "3", "title": "News"}, {"id": "7", "title": "Security"}]"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let timeline = mock(
+            &mut server,
+            "/api/v1/timelines/list/7",
+            "limit=3",
+            page(10, 3),
+        )
+        .await
+        .expect(2);
+
+        let mastodon = mastodon(&server);
+        let query = Query {
+            limit: 3,
+            ..Default::default()
+        };
+        mastodon.list("security", &query).await.unwrap();
+        let posts = mastodon.list("Security", &query).await.unwrap();
+
+        lists.assert_async().await;
+        timeline.assert_async().await;
+        assert_eq!(ids(&posts), vec![10, 9, 8]);
+    }
+
+    #[tokio::test]
+    async fn missing_list_is_an_error() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/api/v1/lists")
+            .with_header("content-type", "application/json")
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let query = Query {
+            limit: 3,
+            ..Default::default()
+        };
+        assert!(mastodon(&server).list("security", &query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn me_pages_back_from_since_id() {
+        let mut server = Server::new_async().await;
+        let path = "/api/v1/accounts/1/statuses";
+        let first = mock(&mut server, path, "limit=40&since_id=10", page(100, 40)).await;
+        let second = mock(
+            &mut server,
+            path,
+            "limit=40&since_id=10&max_id=61",
+            page(60, 40),
+        )
+        .await;
+
+        let query = Query {
+            limit: 45,
+            since: Some("10".to_string()),
+            ..Default::default()
+        };
+        let posts = mastodon(&server).me(&query).await.unwrap();
+
+        first.assert_async().await;
+        second.assert_async().await;
+        assert_eq!(ids(&posts), (56..=100).rev().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_rate_limit() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/api/v1/timelines/home")
+            .match_query(Matcher::Any)
+            .with_header("content-type", "application/json")
+            .with_header("x-ratelimit-remaining", "299")
+            .with_header("x-ratelimit-reset", "2021-10-15T12:05:00.000Z")
+            .with_body(page(5, 1))
+            .create_async()
+            .await;
+
+        let mastodon = mastodon(&server);
+        let query = Query {
+            limit: 1,
+            ..Default::default()
+        };
+        mastodon.home(&query).await.unwrap();
+
+        let limit = mastodon.rate_limit().unwrap();
+        assert_eq!(limit.remaining, 299);
+        assert_eq!(limit.reset, Utc.timestamp_opt(1_634_299_500, 0).unwrap());
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use egg_mode::tweet::Tweet;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tokio::time::Duration;
+
+use crate::backend::{take_newer, Backend, Post, Query, MAX_PAGES, POLL_INTERVAL_MS};
+
+// Pages as big as Twitter's
+const MAX_PAGE_SIZE: usize = 200;
+
+// Plays back tweets saved with --record (or any JSON array of tweets as the
+// Twitter API returns them) so babble can run without credentials. Every
+// timeline is the same saved set of tweets, read a page at a time like the
+// real thing.
+
+pub(crate) struct Replay {
+    // Oldest first
+    posts: Vec<Post>,
+    pace: Option<Duration>,
+    // How many of the posts have "arrived" so far when pacing
+    released: Mutex<usize>,
+    page_size: usize,
+    // Pages read so far, which is how many API calls a real backend would
+    // have made
+    pages_read: AtomicUsize,
+}
+
+impl Replay {
+    pub(crate) fn load(path: &Path, pace: Option<Duration>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let reader = std::io::BufReader::new(file);
+        let tweets: Vec<Tweet> = serde_json::from_reader(reader)?;
+        Ok(Replay::from_posts(
+            tweets.iter().map(Post::from).collect(),
+            pace,
+        ))
+    }
+
+    pub(crate) fn from_posts(mut posts: Vec<Post>, pace: Option<Duration>) -> Self {
+        posts.sort_by_key(|p| p.created_at);
+        Replay {
+            posts,
+            pace,
+            released: Mutex::new(0),
+            page_size: MAX_PAGE_SIZE,
+            pages_read: AtomicUsize::new(0),
+        }
+    }
+
+    fn release(&self, limit: usize) -> usize {
+        let mut released = self.released.lock().unwrap();
+        *released = match self.pace {
+            // Start with a page like a real fetch would and then trickle
+            // out one post per poll
+            Some(_) if *released == 0 => limit,
+            Some(_) => *released + 1,
+            None => self.posts.len(),
+        }
+        .min(self.posts.len());
+        *released
+    }
+}
+
+#[async_trait]
+impl Backend for Replay {
+    fn handle(&self) -> &str {
+        "replay"
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.pace
+            .unwrap_or_else(|| Duration::from_millis(POLL_INTERVAL_MS))
+    }
+
+    async fn me(&self, query: &Query) -> Result<Vec<Post>> {
+        self.home(query).await
+    }
+
+    async fn list(&self, _name: &str, query: &Query) -> Result<Vec<Post>> {
+        self.home(query).await
+    }
+
+    async fn home(&self, query: &Query) -> Result<Vec<Post>> {
+        let released = self.release(query.limit);
+        let newest_first: Vec<&Post> = self.posts[..released].iter().rev().collect();
+        let mut posts: Vec<Post> = vec![];
+        for page in newest_first
+            .chunks(query.page_size(self.page_size))
+            .take(MAX_PAGES)
+        {
+            self.pages_read.fetch_add(1, Ordering::Relaxed);
+            posts.extend(page.iter().map(|p| (*p).clone()));
+            if !query.wants_more(&posts, page.len(), self.page_size) {
+                break;
+            }
+        }
+        Ok(query.keep(take_newer(posts, &query.since)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::test_post;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn minute(i: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + i * 60, 0).unwrap()
+    }
+
+    // A post a minute for the last hundred minutes, read ten at a time
+    fn replay() -> Replay {
+        let posts = (0..100)
+            .map(|i| test_post(&i.to_string(), minute(i)))
+            .collect();
+        Replay {
+            page_size: 10,
+            ..Replay::from_posts(posts, None)
+        }
+    }
+
+    async fn fetch(replay: &Replay, query: Query) -> (Vec<String>, usize) {
+        let posts = replay.home(&query).await.unwrap();
+        (
+            posts.into_iter().map(|p| p.id).collect(),
+            replay.pages_read.load(Ordering::Relaxed),
+        )
+    }
+
+    fn ids(newest: i64, oldest: i64) -> Vec<String> {
+        (oldest..=newest).rev().map(|i| i.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn one_page_when_it_holds_the_limit() {
+        let query = Query {
+            limit: 5,
+            ..Default::default()
+        };
+        assert_eq!(query.page_size(10), 5);
+        assert_eq!(fetch(&replay(), query).await, (ids(99, 95), 1));
+    }
+
+    #[tokio::test]
+    async fn pages_until_it_has_the_limit() {
+        let query = Query {
+            limit: 25,
+            ..Default::default()
+        };
+        assert_eq!(query.page_size(10), 10);
+        assert_eq!(fetch(&replay(), query).await, (ids(99, 75), 3));
+    }
+
+    #[tokio::test]
+    async fn stops_when_the_posts_run_out() {
+        let query = Query {
+            limit: 1000,
+            ..Default::default()
+        };
+        let (ids, pages) = fetch(&replay(), query).await;
+        assert_eq!((ids.len(), pages), (100, 10));
+    }
+
+    #[tokio::test]
+    async fn stops_paging_at_after() {
+        let query = Query {
+            limit: 5,
+            after: Some(minute(75)),
+            ..Default::default()
+        };
+        assert_eq!(query.page_size(10), 10);
+        // Everything since, however many that is, from the three pages
+        // that reach back to it
+        assert_eq!(fetch(&replay(), query).await, (ids(99, 75), 3));
+    }
+
+    #[tokio::test]
+    async fn pages_past_before() {
+        let query = Query {
+            limit: 5,
+            before: Some(minute(50)),
+            ..Default::default()
+        };
+        // Five pages newer than the range and one that has it
+        assert_eq!(fetch(&replay(), query).await, (ids(49, 45), 6));
+    }
+
+    #[tokio::test]
+    async fn covers_the_whole_range() {
+        let query = Query {
+            limit: 5,
+            after: Some(minute(20)),
+            before: Some(minute(30)),
+            ..Default::default()
+        };
+        assert_eq!(fetch(&replay(), query).await, (ids(29, 20), 8));
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::{
+    backend::{append_link, shift_entities, Author, Backend, Link, Media, Post, Query, Tag},
+    html, MyConfig,
+};
+
+// Reads RSS 2.0 and Atom feeds from URLs or local files. Feed groups from
+// the config stand in for lists and home is every feed in every group.
+
+const CONNECT_SECS: u64 = 5;
+// The longest one feed may take, so a hung server can't hold up its group
+const FETCH_SECS: u64 = 20;
+
+pub(crate) struct Rss {
+    client: reqwest::Client,
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Rss {
+    pub(crate) async fn init(cfg: &MyConfig) -> Result<Self> {
+        if cfg.feeds.is_empty() {
+            return Err(anyhow!("No feed groups configured"));
+        }
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_SECS))
+            .timeout(Duration::from_secs(FETCH_SECS))
+            .build()?;
+        Ok(Rss {
+            client,
+            groups: cfg
+                .feeds
+                .iter()
+                .map(|(name, sources)| (name.to_lowercase(), sources.clone()))
+                .collect(),
+        })
+    }
+
+    async fn fetch_sources(&self, sources: &[&String], query: &Query) -> Result<Vec<Post>> {
+        let mut posts: Vec<Post> = vec![];
+        for source in sources {
+            match self.fetch_source(source).await {
+                Ok(mut feed_posts) => posts.append(&mut feed_posts),
+                // One dead feed shouldn't take down the whole group
+                Err(err) => warn!("Failed to read feed {}: {}", source, err),
+            }
+        }
+        posts.sort_by(|a, b| order(a).cmp(&order(b)));
+        // Feeds have no notion of since or paging, so filter here
+        Ok(query.keep(newer_than(posts, query)))
+    }
+
+    async fn fetch_source(&self, source: &str) -> Result<Vec<Post>> {
+        let body: Vec<u8> = if source.starts_with("http://") || source.starts_with("https://") {
+            self.client
+                .get(source)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec()
+        } else {
+            std::fs::read(source)?
+        };
+        let feed = feed_rs::parser::parse(&body[..])?;
+
+        let author = Author {
+            handle: feed
+                .links
+                .iter()
+                .find_map(|l| reqwest::Url::parse(&l.href).ok())
+                .and_then(|url| url.host_str().map(|h| h.to_string()))
+                .unwrap_or_else(|| source.to_string()),
+            name: feed
+                .title
+                .as_ref()
+                .map(|t| t.content.clone())
+                .unwrap_or_else(|| source.to_string()),
+            url: feed
+                .links
+                .first()
+                .map(|l| l.href.clone())
+                .unwrap_or_default(),
+        };
+
+        // Undated entries need a date that stays put from one poll to the next
+        let updated = feed
+            .updated
+            .unwrap_or_else(|| DateTime::<Utc>::from(std::time::UNIX_EPOCH));
+        Ok(feed
+            .entries
+            .iter()
+            .map(|entry| to_post(entry, &author, updated))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Backend for Rss {
+    fn handle(&self) -> &str {
+        "feeds"
+    }
+
+    async fn me(&self, _query: &Query) -> Result<Vec<Post>> {
+        Err(anyhow!("Feeds have no account to show"))
+    }
+
+    async fn list(&self, group: &str, query: &Query) -> Result<Vec<Post>> {
+        let sources = self
+            .groups
+            .get(&group.to_lowercase())
+            .ok_or(anyhow!("Feed group not found"))?;
+        self.fetch_sources(&sources.iter().collect::<Vec<_>>(), query)
+            .await
+    }
+
+    async fn home(&self, query: &Query) -> Result<Vec<Post>> {
+        let mut sources: Vec<&String> = self.groups.values().flatten().collect();
+        sources.sort();
+        sources.dedup();
+        self.fetch_sources(&sources, query).await
+    }
+}
+
+// Newest first, with the id breaking ties between entries from the same moment
+fn order(post: &Post) -> (std::cmp::Reverse<DateTime<Utc>>, &str) {
+    (std::cmp::Reverse(post.created_at), &post.id)
+}
+
+// Entries that sort before the one with the since id. When that entry has
+// dropped off the feed, or its feed couldn't be read this time, entries
+// made after it are new.
+fn newer_than(posts: Vec<Post>, query: &Query) -> Vec<Post> {
+    let cursor = match posts.iter().find(|p| Some(&p.id) == query.since.as_ref()) {
+        Some(post) => (post.created_at, post.id.clone()),
+        None => {
+            return match query.since_time {
+                Some(since) => posts.into_iter().filter(|p| p.created_at > since).collect(),
+                None => posts,
+            }
+        }
+    };
+    posts
+        .into_iter()
+        .filter(|p| order(p) < (std::cmp::Reverse(cursor.0), cursor.1.as_str()))
+        .collect()
+}
+
+fn to_post(entry: &feed_rs::model::Entry, feed_author: &Author, updated: DateTime<Utc>) -> Post {
+    let link: Option<String> = entry
+        .links
+        .iter()
+        .find(|l| l.rel.as_deref().unwrap_or("alternate") == "alternate")
+        .or_else(|| entry.links.first())
+        .map(|l| l.href.clone());
+
+    // Title, then the summary (or content when there's no summary), then
+    // the link to the full article
+    let mut text = entry
+        .title
+        .as_ref()
+        .map(|t| html::to_text(&t.content).0)
+        .unwrap_or_default();
+    let body = entry
+        .summary
+        .as_ref()
+        .map(|s| s.content.clone())
+        .or_else(|| entry.content.as_ref().and_then(|c| c.body.clone()));
+    let mut urls: Vec<Link> = vec![];
+    let mut tags: Vec<Tag> = vec![];
+    if let Some(body) = body {
+        let (body_text, mut body_urls, mut body_tags) = html::to_text(&body);
+        if !body_text.is_empty() {
+            text.push('\n');
+            shift_entities(&mut body_urls, &mut body_tags, text.len());
+            text.push_str(&body_text);
+            urls.append(&mut body_urls);
+            tags.append(&mut body_tags);
+        }
+    }
+    if let Some(ref link) = link {
+        urls.push(Link {
+            url: link.clone(),
+            display_url: link.clone(),
+            expanded_url: None,
+            range: Some(append_link(&mut text, link)),
+        });
+    }
+
+    let media: Vec<Media> = entry
+        .media
+        .iter()
+        .flat_map(|m| m.thumbnails.iter())
+        .map(|t| Media {
+            url: t.image.uri.clone(),
+            display_url: t.image.uri.clone(),
+            media_url: t.image.uri.clone(),
+            range: Some(append_link(&mut text, &t.image.uri)),
+        })
+        .collect();
+
+    Post {
+        id: if entry.id.is_empty() {
+            link.clone().unwrap_or_default()
+        } else {
+            entry.id.clone()
+        },
+        author: match entry.authors.first() {
+            Some(person) => Author {
+                name: format!("{} ({})", &person.name, &feed_author.name),
+                ..feed_author.clone()
+            },
+            None => feed_author.clone(),
+        },
+        created_at: entry.published.or(entry.updated).unwrap_or(updated),
+        text,
+        urls,
+        media,
+        tags,
+        repost_count: 0,
+        like_count: 0,
+        source: None,
+        place: None,
+        reply_to: None,
+        reposted: None,
+        quoted: None,
+        activity: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A feed file of its own for each test, as tests run in parallel
+    fn feed(name: &str, items: &[(&str, Option<&str>)]) -> Rss {
+        let items: String = items
+            .iter()
+            .map(|(id, date)| {
+                let date = date
+                    .map(|d| format!("<pubDate>{}</pubDate>", d))
+                    .unwrap_or_default();
+                format!(
+                    "<item><guid>{}</guid><title>{}</title>{}</item>",
+                    id, id, date
+                )
+            })
+            .collect();
+        let path =
+            std::env::temp_dir().join(format!("babble_rss_{}_{}.xml", std::process::id(), name));
+        std::fs::write(
+            &path,
+            format!(
+                "<rss version=\"2.0\"><channel><title>Test</title>\
+                 <lastBuildDate>Tue, 01 Jun 2021 12:00:00 GMT</lastBuildDate>\
+                 {}</channel></rss>",
+                items
+            ),
+        )
+        .unwrap();
+        let mut groups = BTreeMap::new();
+        groups.insert("test".to_string(), vec![path.display().to_string()]);
+        Rss {
+            client: reqwest::Client::new(),
+            groups,
+        }
+    }
+
+    fn since(id: Option<&str>) -> Query {
+        Query {
+            limit: 10,
+            since: id.map(|id| id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn ids(posts: &[Post]) -> Vec<&str> {
+        posts.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn undated_entries_take_the_feed_date() {
+        let rss = feed("undated", &[("b", None), ("a", None)]);
+        let first = rss.home(&since(None)).await.unwrap();
+        let second = rss.home(&since(None)).await.unwrap();
+
+        assert_eq!(ids(&first), vec!["a", "b"]);
+        assert_eq!(
+            first[0].created_at.to_rfc3339(),
+            "2021-06-01T12:00:00+00:00"
+        );
+        assert_eq!(first[0].created_at, second[0].created_at);
+        assert!(rss.home(&since(Some("a"))).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn picks_up_after_the_cursor_entry() {
+        let old = "Mon, 31 May 2021 12:00:00 GMT";
+        let new = "Wed, 02 Jun 2021 12:00:00 GMT";
+        let rss = feed("cursor", &[("old", Some(old)), ("undated", None)]);
+        let posts = rss.home(&since(None)).await.unwrap();
+        assert_eq!(ids(&posts), vec!["undated", "old"]);
+
+        let rss = feed(
+            "cursor",
+            &[("old", Some(old)), ("new", Some(new)), ("undated", None)],
+        );
+        let posts = rss.home(&since(Some("undated"))).await.unwrap();
+        assert_eq!(ids(&posts), vec!["new"]);
+    }
+
+    #[tokio::test]
+    async fn goes_by_time_when_the_cursor_entry_is_gone() {
+        let rss = feed(
+            "gone",
+            &[
+                ("old", Some("Mon, 31 May 2021 12:00:00 GMT")),
+                ("new", Some("Wed, 02 Jun 2021 12:00:00 GMT")),
+            ],
+        );
+        let query = Query {
+            since_time: Some("2021-06-01T00:00:00Z".parse().unwrap()),
+            ..since(Some("dropped"))
+        };
+        let posts = rss.home(&query).await.unwrap();
+        assert_eq!(ids(&posts), vec!["new"]);
+    }
+}
@@ -0,0 +1,332 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Post text arrives with accents as separate combining characters and the
+// odd control character. Everything the formatters show from a post's text
+// goes through here once it's been split at the entities.
+
+/// Composes combining characters onto their base and drops control
+/// characters that would upset the terminal
+pub(crate) fn normalize(s: &str) -> String {
+    s.nfc().filter(|c| keep(*c)).collect()
+}
+
+fn keep(c: char) -> bool {
+    match c {
+        '\n' | '\t' => true,
+        // Zero width joiners and variation selectors aren't control
+        // characters, so emoji sequences come through whole
+        c => !c.is_control(),
+    }
+}
+
+/// What closes the bidi embeddings, overrides and isolates the text leaves
+/// open, so they don't carry on past the end of the post and flip the rest
+/// of the screen
+pub(crate) fn close_bidi(text: &str) -> String {
+    // True for an isolate, false for an embedding or override
+    let mut open: Vec<bool> = Vec::new();
+    for c in text.chars() {
+        match c {
+            '\u{202a}' | '\u{202b}' | '\u{202d}' | '\u{202e}' => open.push(false),
+            // A PDF can't close anything outside the innermost isolate
+            '\u{202c}' if open.last() == Some(&false) => {
+                open.pop();
+            }
+            '\u{2066}'..='\u{2068}' => open.push(true),
+            // A PDI closes its isolate and everything opened inside it
+            '\u{2069}' if open.contains(&true) => while open.pop() == Some(false) {},
+            _ => (),
+        }
+    }
+    open.iter()
+        .rev()
+        .map(|&isolate| if isolate { '\u{2069}' } else { '\u{202c}' })
+        .collect()
+}
+
+/// The width to wrap to: the one asked for (0 meaning don't wrap), or the
+/// terminal's width as it is now when printing to one
+pub(crate) fn wrap_width(width: Option<usize>) -> Option<usize> {
+    match width {
+        Some(0) => None,
+        Some(width) => Some(width),
+        None if termion::is_tty(&std::io::stdout()) => {
+            termion::terminal_size().ok().map(|(cols, _)| cols as usize)
+        }
+        None => None,
+    }
+}
+
+/// Lays out (text, style) spans in `width` columns, starting each line with
+/// `indent`. Styles are escape codes put back around every piece of a span
+/// that lands on a new line, and `reset` ends them. Widths are in terminal
+/// columns, so wide CJK characters and emoji count double and are allowed
+/// to break between each other. Links are never broken.
+pub(crate) fn wrap(spans: &[(String, &str)], width: usize, indent: &str, reset: &str) -> String {
+    let room = width.saturating_sub(indent.width()).max(MIN_ROOM);
+    let mut out = String::new();
+    // Columns used on the current line, not counting the indent
+    let mut col = 0;
+    // Nothing on this line yet, not even the indent, so blank lines stay blank
+    let mut fresh = true;
+    // Whitespace waiting to go before the next word if it fits on the line
+    let mut space = String::new();
+
+    for (text, style) in spans {
+        for piece in pieces(text) {
+            match piece {
+                Piece::Newline => {
+                    out.push('\n');
+                    fresh = true;
+                    col = 0;
+                    space.clear();
+                }
+                Piece::Space(s) => space.push_str(s),
+                Piece::Word(word) => {
+                    for part in parts(word, room) {
+                        let part_width = part.width();
+                        if col > 0 && col + space.width() + part_width > room {
+                            out.push('\n');
+                            out.push_str(indent);
+                            col = 0;
+                        } else {
+                            if fresh {
+                                out.push_str(indent);
+                            }
+                            out.push_str(&space);
+                            col += space.width();
+                        }
+                        fresh = false;
+                        space.clear();
+                        if style.is_empty() {
+                            out.push_str(part);
+                        } else {
+                            out.push_str(style);
+                            out.push_str(part);
+                            out.push_str(reset);
+                        }
+                        col += part_width;
+                    }
+                }
+            }
+        }
+    }
+    // Don't leave an indent dangling after a trailing newline
+    out.truncate(out.trim_end().len());
+    out
+}
+
+/// Cuts a line with escape codes in it down to `width` columns, or pads it
+/// out with spaces to cover whatever was on screen there before
+pub(crate) fn fit(line: &str, width: usize, reset: &str) -> String {
+    let mut out = String::new();
+    let mut col = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Copy escape codes through whole, they take no room
+            out.push(c);
+            if chars.peek() == Some(&'[') {
+                out.push(chars.next().unwrap_or('['));
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        let c = if c == '\t' { ' ' } else { c };
+        let c_width = c.width().unwrap_or(0);
+        if col + c_width > width {
+            break;
+        }
+        out.push(c);
+        col += c_width;
+    }
+    out.push_str(reset);
+    out.push_str(&" ".repeat(width.saturating_sub(col)));
+    out
+}
+
+// Narrower than this and wrapping does more harm than good
+const MIN_ROOM: usize = 20;
+
+enum Piece<'a> {
+    Newline,
+    Space(&'a str),
+    Word(&'a str),
+}
+
+fn pieces(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut in_space = false;
+    for (i, c) in text.char_indices() {
+        let is_space = c.is_whitespace();
+        if c == '\n' || (i > start && is_space != in_space) {
+            if i > start {
+                pieces.push(piece(&text[start..i], in_space));
+            }
+            start = i;
+        }
+        if c == '\n' {
+            pieces.push(Piece::Newline);
+            start = i + 1;
+        }
+        in_space = is_space;
+    }
+    if start < text.len() {
+        pieces.push(piece(&text[start..], in_space));
+    }
+    pieces
+}
+
+fn piece(s: &str, space: bool) -> Piece<'_> {
+    if space {
+        Piece::Space(s)
+    } else {
+        Piece::Word(s)
+    }
+}
+
+// Where a word may be broken: either side of every wide character, and
+// anywhere in a word too long for a line unless it's a link
+fn parts(word: &str, room: usize) -> Vec<&str> {
+    if word.starts_with("http://") || word.starts_with("https://") {
+        return vec![word];
+    }
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut col = 0;
+    for (i, grapheme) in word.grapheme_indices(true) {
+        let width = grapheme.width();
+        if i > start && (width > 1 || col + width > room) {
+            parts.push(&word[start..i]);
+            start = i;
+            col = 0;
+        }
+        col += width;
+        if width > 1 {
+            parts.push(&word[start..i + grapheme.len()]);
+            start = i + grapheme.len();
+            col = 0;
+        }
+    }
+    if start < word.len() {
+        parts.push(&word[start..]);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_entities_alone() {
+        // Decoding is up to the backend, so a literal &lt; stays as written
+        assert_eq!(normalize("&amp;lt; &lt;"), "&amp;lt; &lt;");
+    }
+
+    #[test]
+    fn composes_and_drops_controls() {
+        assert_eq!(normalize("cafe\u{301}\u{7}\tok\n"), "caf\u{e9}\tok\n");
+    }
+
+    #[test]
+    fn keeps_right_to_left_marks() {
+        let text = "\u{2067}שלום\u{2069} world";
+        assert_eq!(normalize(text), text);
+        assert_eq!(close_bidi(text), "");
+    }
+
+    #[test]
+    fn closes_what_is_left_open() {
+        assert_eq!(close_bidi("\u{202b}abc"), "\u{202c}");
+        assert_eq!(close_bidi("\u{2067}abc\u{202e}def"), "\u{202c}\u{2069}");
+        // A PDI closes the embedding opened inside its isolate
+        assert_eq!(close_bidi("\u{202b}\u{2066}a\u{202d}b\u{2069}"), "\u{202c}");
+        // A PDF can't reach past an isolate
+        assert_eq!(close_bidi("\u{202b}\u{2066}a\u{202c}"), "\u{2069}\u{202c}");
+        // Stray closers have nothing to close
+        assert_eq!(close_bidi("a\u{202c}\u{2069}"), "");
+    }
+
+    const RED: &str = "\x1b[31m";
+    const RESET: &str = "\x1b[0m";
+
+    fn plain(text: &str, width: usize, indent: &str) -> String {
+        wrap(&[(text.to_string(), "")], width, indent, RESET)
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(
+            plain("aaaa bbbb cccc dddd eeee", 20, ""),
+            "aaaa bbbb cccc dddd\neeee"
+        );
+    }
+
+    #[test]
+    fn hanging_indent_on_every_line_but_blank_ones() {
+        assert_eq!(
+            plain("aaaa bbbb cccc dddd eeee\n\nffff\n", 22, "  "),
+            "  aaaa bbbb cccc dddd\n  eeee\n\n  ffff"
+        );
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        assert_eq!(
+            plain("一二三四五六七八九十一二", 20, ""),
+            "一二三四五六七八九十\n一二"
+        );
+        assert_eq!(
+            plain("aaaa bbbb cccc dd 日本", 20, ""),
+            "aaaa bbbb cccc dd 日\n本"
+        );
+    }
+
+    #[test]
+    fn combining_characters_stay_with_their_base() {
+        let word = "e\u{301}".repeat(25);
+        let wrapped = plain(&word, 20, "");
+        let lines: Vec<&str> = wrapped.lines().collect();
+        assert_eq!(lines, vec!["e\u{301}".repeat(20), "e\u{301}".repeat(5)]);
+    }
+
+    #[test]
+    fn links_are_never_broken() {
+        let url = format!("https://example.com/{}", "a".repeat(30));
+        assert_eq!(
+            plain(&format!("see {}", url), 20, ""),
+            format!("see\n{}", url)
+        );
+    }
+
+    #[test]
+    fn styles_are_reset_before_each_line_break() {
+        let spans = vec![
+            ("aaaa bbbb cccc dddd ".to_string(), ""),
+            ("eeee ffff".to_string(), RED),
+        ];
+        assert_eq!(
+            wrap(&spans, 20, "", RESET),
+            format!("aaaa bbbb cccc dddd\n{0}eeee{1} {0}ffff{1}", RED, RESET)
+        );
+        assert_eq!(
+            wrap(&[("x".repeat(25), RED)], 20, "", RESET),
+            format!(
+                "{0}{2}{1}\n{0}{3}{1}",
+                RED,
+                RESET,
+                "x".repeat(20),
+                "x".repeat(5)
+            )
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use termion::{color, style};
+
+// Colors for the terminal output. A theme is a color spec per element and
+// the config file can override any of them. A spec is any of bold, dim,
+// italic, underline, a 16-color name (blue, bright-blue), a 256-color index
+// (208) or truecolor hex (#ff8800), separated by spaces.
+
+/// Per-element color specs from the `[colors]` table of the config file
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Colors {
+    pub(crate) user: Option<String>,
+    pub(crate) time: Option<String>,
+    pub(crate) url: Option<String>,
+    pub(crate) meta: Option<String>,
+    pub(crate) hashtag: Option<String>,
+}
+
+/// Escape codes to start each element with, empty for no color
+#[derive(Default, Debug, Clone)]
+pub(crate) struct Theme {
+    pub(crate) user: String,
+    pub(crate) time: String,
+    pub(crate) url: String,
+    pub(crate) meta: String,
+    pub(crate) hashtag: String,
+}
+
+// Specs for user, time, url, meta and hashtag in that order
+const THEMES: &[(&str, [&str; 5])] = &[
+    (
+        "default",
+        ["bold blue", "yellow", "underline blue", "red", "yellow"],
+    ),
+    // Darker colors that stay readable on a white background
+    ("light", ["bold 18", "94", "underline 25", "124", "28"]),
+    ("mono", ["bold", "", "underline", "dim", "bold"]),
+];
+
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+pub(crate) fn names() -> Vec<&'static str> {
+    THEMES.iter().map(|(name, _)| *name).collect()
+}
+
+impl Theme {
+    pub(crate) fn load(name: &str, overrides: &Colors) -> Result<Theme> {
+        let specs = THEMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, specs)| specs)
+            .ok_or(anyhow!(
+                "Unknown theme {}, expected one of {}",
+                name,
+                names().join(", ")
+            ))?;
+        let pick = |over: &Option<String>, spec: &str| -> Result<String> {
+            parse(over.as_deref().unwrap_or(spec))
+        };
+        Ok(Theme {
+            user: pick(&overrides.user, specs[0])?,
+            time: pick(&overrides.time, specs[1])?,
+            url: pick(&overrides.url, specs[2])?,
+            meta: pick(&overrides.meta, specs[3])?,
+            hashtag: pick(&overrides.hashtag, specs[4])?,
+        })
+    }
+}
+
+fn parse(spec: &str) -> Result<String> {
+    let mut codes = String::new();
+    for word in spec.split_whitespace() {
+        let word = word.to_lowercase();
+        let code = match word.as_str() {
+            "bold" => style::Bold.to_string(),
+            "dim" => style::Faint.to_string(),
+            "italic" => style::Italic.to_string(),
+            "underline" => style::Underline.to_string(),
+            "none" => "".to_string(),
+            hex if hex.starts_with('#') => {
+                let rgb = u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .filter(|_| hex.len() == 7)
+                    .ok_or(anyhow!("Bad hex color {}", hex))?;
+                color::Fg(color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)).to_string()
+            }
+            index if index.parse::<u8>().is_ok() => {
+                color::Fg(color::AnsiValue(index.parse::<u8>()?)).to_string()
+            }
+            name => {
+                let (bright, base) = match name
+                    .strip_prefix("bright-")
+                    .or_else(|| name.strip_prefix("light-"))
+                {
+                    Some(base) => (true, base),
+                    None => (false, name),
+                };
+                let index = NAMES.iter().position(|n| *n == base).ok_or(anyhow!(
+                    "Unknown color {} in \"{}\"",
+                    name,
+                    spec
+                ))?;
+                // The same codes termion uses for color::Blue, color::LightBlue and so on
+                color::Fg(color::AnsiValue(index as u8 + if bright { 8 } else { 0 })).to_string()
+            }
+        };
+        codes.push_str(&code);
+    }
+    Ok(codes)
+}
+
+/// When to use color, from --color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ColorWhen {
+    Never,
+    Auto,
+    Always,
+}
+
+impl FromStr for ColorWhen {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ColorWhen> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(ColorWhen::Never),
+            "auto" => Ok(ColorWhen::Auto),
+            "always" => Ok(ColorWhen::Always),
+            _ => Err(anyhow!("Expected never, auto or always, not {}", s)),
+        }
+    }
+}
+
+/// Auto means color only when printing to a terminal and NO_COLOR isn't set
+/// (see https://no-color.org)
+pub(crate) fn enabled(when: ColorWhen) -> bool {
+    match when {
+        ColorWhen::Never => false,
+        ColorWhen::Always => true,
+        ColorWhen::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                && termion::is_tty(&std::io::stdout())
+        }
+    }
+}
@@ -0,0 +1,417 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use rand::Rng;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::{
+    backend::{retry, Activity, Backend, Feed, Post, Query, Retry},
+    cursor::{Cursor, Place},
+    hints::Hints,
+    keys::Controls,
+    ui::UI,
+};
+
+// Waits after failed fetches start here and double up to the cap
+const BACKOFF_START_SECS: u64 = 5;
+const BACKOFF_MAX_SECS: u64 = 15 * 60;
+// Adaptive polling stays within these, starting from the backend's interval
+const ADAPTIVE_MIN_SECS: u64 = 15;
+const ADAPTIVE_MAX_SECS: u64 = 10 * 60;
+
+/// How often to poll when streaming, from --interval
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Interval {
+    Fixed(Duration),
+    /// Faster while posts keep coming and slower when the feed is quiet
+    Adaptive,
+}
+
+/// How to stream, beyond what to fetch
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) interval: Option<Interval>,
+    /// Where to save the newest post seen
+    pub(crate) cursor: Option<Cursor>,
+    /// Start from the saved cursor, fetching at most this many posts to
+    /// catch up
+    pub(crate) catch_up: Option<usize>,
+    /// The labels shown after links, to open them by
+    pub(crate) hints: Option<Arc<Hints>>,
+    /// Command to open links with
+    pub(crate) opener: String,
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Interval> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Interval::Adaptive);
+        }
+        match s.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Interval::Fixed(Duration::from_secs(secs))),
+            _ => Err(anyhow!("Expected a number of seconds or auto, not {}", s)),
+        }
+    }
+}
+
+pub(crate) async fn timeline_print(
+    backend: &dyn Backend,
+    feed: &Feed,
+    ui: &UI,
+    query: Query,
+) -> Result<()> {
+    let posts = backend.fetch(feed, &query).await?.posts;
+    ui.print_header();
+    print_feed(ui, posts.iter().rev()).await;
+    ui.print_footer();
+    Ok(())
+}
+
+pub(crate) async fn timeline_stream(
+    backend: &dyn Backend,
+    feed: &Feed,
+    ui: &UI,
+    query: Query,
+    options: Options,
+) -> Result<()> {
+    let mut controls = Controls::new(ui, options.hints.clone(), &options.opener);
+    let mut poller = Poller::new(backend, feed, query, options);
+    ui.print_header();
+    loop {
+        let poll = poller.poll().await?;
+        if let Some(ref posts) = poll.posts {
+            controls.show(posts).await;
+        }
+        if let Some(ref status) = poll.status {
+            ui.print_status(status);
+        }
+        if !controls.wait(poll.wait).await {
+            break;
+        }
+    }
+    drop(controls);
+    ui.print_footer();
+    Ok(())
+}
+
+/// What came of one poll of a stream
+pub(crate) struct Poll {
+    /// New posts, newest first, or None when the fetch failed
+    pub(crate) posts: Option<Vec<Post>>,
+    /// Why the stream is waiting longer than usual, or a note on what was
+    /// fetched
+    pub(crate) status: Option<String>,
+    /// How long to wait before polling again
+    pub(crate) wait: Duration,
+}
+
+/// Keeps a feed's place between polls and works out how long to wait after
+/// each one, through rate limits and failures
+pub(crate) struct Poller<'a> {
+    backend: &'a dyn Backend,
+    feed: &'a Feed,
+    query: Query,
+    options: Options,
+    limit: usize,
+    catching_up: bool,
+    failures: u32,
+    wait: Duration,
+}
+
+impl<'a> Poller<'a> {
+    pub(crate) fn new(
+        backend: &'a dyn Backend,
+        feed: &'a Feed,
+        mut query: Query,
+        options: Options,
+    ) -> Poller<'a> {
+        let limit = query.limit;
+        let mut catching_up = false;
+        if let (Some(cursor), Some(catch_up)) = (&options.cursor, options.catch_up) {
+            if let Some(place) = cursor.load() {
+                query.since = Some(place.id);
+                query.since_time = place.time;
+                query.liked_since = cursor.likes().load().map(|place| place.id);
+                // One more than needed shows whether any were left out
+                query.limit = catch_up + 1;
+                catching_up = true;
+            }
+        }
+        Poller {
+            backend,
+            feed,
+            query,
+            options,
+            limit,
+            catching_up,
+            failures: 0,
+            wait: backend.poll_interval(),
+        }
+    }
+
+    /// Fetches what's new. Only errors that retrying won't fix are returned.
+    pub(crate) async fn poll(&mut self) -> Result<Poll> {
+        let fetched = match self.backend.fetch(self.feed, &self.query).await {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                let (wait, status) = match retry(&err) {
+                    Retry::At(reset) => (until(reset), format!("{}, waiting until", err)),
+                    Retry::Backoff => (backoff(self.failures), format!("{}, retrying at", err)),
+                    Retry::Never => return Err(err),
+                };
+                self.failures += 1;
+                return Ok(Poll {
+                    posts: None,
+                    status: Some(format!("{} {}", status, resume_time(wait))),
+                    wait,
+                });
+            }
+        };
+        self.failures = 0;
+        let mut posts = fetched.posts;
+        // Likes have a place of their own, so a like of an old post doesn't
+        // take the stream back to it
+        if let Some(newest) = posts.iter().find(|p| p.activity != Some(Activity::Liked)) {
+            let place = Place {
+                id: newest.id.clone(),
+                time: Some(newest.created_at),
+            };
+            self.query.since = Some(place.id.clone());
+            self.query.since_time = place.time;
+            save(self.options.cursor.as_ref(), &place);
+        }
+        if let Some(newest_like) = fetched.newest_like {
+            let cursor = self.options.cursor.as_ref().map(Cursor::likes);
+            let place = Place {
+                id: newest_like,
+                time: None,
+            };
+            save(cursor.as_ref(), &place);
+            self.query.liked_since = Some(place.id);
+        }
+        self.query.limit = self.limit;
+        // Anything older than the start of the range was covered by the
+        // first fetch
+        self.query.after = None;
+
+        let mut status = None;
+        let catch_up = self.options.catch_up.unwrap_or_default();
+        if self.catching_up && posts.len() > catch_up {
+            posts.truncate(catch_up);
+            status = Some(format!(
+                "Caught up on the latest {} posts, older ones were skipped",
+                posts.len()
+            ));
+        }
+        self.catching_up = false;
+
+        self.wait = next_wait(self.backend, self.options.interval, self.wait, posts.len());
+        debug!("Polling again in {}s", self.wait.as_secs());
+        let mut wait = self.wait;
+        if let Some(limit) = self.backend.rate_limit() {
+            if limit.remaining == 0 && until(limit.reset) > wait {
+                wait = until(limit.reset);
+                status = Some(format!(
+                    "Rate limit used up, waiting until {}",
+                    resume_time(wait)
+                ));
+            }
+        }
+        Ok(Poll {
+            posts: Some(posts),
+            status,
+            wait,
+        })
+    }
+}
+
+fn save(cursor: Option<&Cursor>, place: &Place) {
+    if let Some(cursor) = cursor {
+        if let Err(err) = cursor.save(place) {
+            warn!("Couldn't save where the stream is up to: {}", err);
+        }
+    }
+}
+
+fn next_wait(
+    backend: &dyn Backend,
+    interval: Option<Interval>,
+    last: Duration,
+    new_posts: usize,
+) -> Duration {
+    match interval {
+        None => backend.poll_interval(),
+        Some(Interval::Fixed(every)) => every,
+        Some(Interval::Adaptive) => {
+            let wait = if new_posts > 0 {
+                last * 2 / 3
+            } else {
+                last * 3 / 2
+            }
+            .max(Duration::from_secs(ADAPTIVE_MIN_SECS))
+            .min(Duration::from_secs(ADAPTIVE_MAX_SECS));
+            // Spread the requests left over the rest of the window. Panes
+            // sharing a token see each other's use in what's left, so they
+            // slow down together.
+            match backend.rate_limit() {
+                Some(limit) if limit.remaining > 0 => {
+                    wait.max(until(limit.reset) / limit.remaining)
+                }
+                _ => wait,
+            }
+        }
+    }
+}
+
+// A little past the reset so the window has surely rolled over
+fn until(reset: DateTime<Utc>) -> Duration {
+    (reset - Utc::now())
+        .to_std()
+        .unwrap_or_default()
+        .saturating_add(Duration::from_secs(1))
+}
+
+// Exponential with jitter, so a crowd of clients hit by the same outage
+// don't all come back at once
+fn backoff(failures: u32) -> Duration {
+    let secs = BACKOFF_START_SECS
+        .saturating_mul(1 << failures.min(16))
+        .min(BACKOFF_MAX_SECS);
+    let jittered = rand::thread_rng().gen_range(secs / 2..=secs);
+    Duration::from_secs(jittered)
+}
+
+fn resume_time(wait: Duration) -> String {
+    let at =
+        Utc::now() + chrono::Duration::from_std(wait).unwrap_or_else(|_| chrono::Duration::zero());
+    at.with_timezone(&Local).format("%H:%M:%S").to_string()
+}
+
+async fn print_feed<'a, I>(ui: &UI, feed: I)
+where
+    I: Iterator<Item = &'a Post>,
+{
+    let statuses: Vec<&Post> = feed.collect();
+    ui.print_tweets(&statuses).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::test_post;
+    use crate::replay::Replay;
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+    use std::sync::Mutex;
+
+    fn minute(i: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + i * 60, 0).unwrap()
+    }
+
+    // An account that posts now and then and likes an old post
+    struct Account {
+        posts: Mutex<Vec<Post>>,
+        likes: Vec<Post>,
+    }
+
+    #[async_trait]
+    impl Backend for Account {
+        fn handle(&self) -> &str {
+            "alice"
+        }
+
+        async fn home(&self, _query: &Query) -> Result<Vec<Post>> {
+            Ok(vec![])
+        }
+
+        async fn list(&self, _name: &str, _query: &Query) -> Result<Vec<Post>> {
+            Ok(vec![])
+        }
+
+        async fn me(&self, query: &Query) -> Result<Vec<Post>> {
+            let posts = self.posts.lock().unwrap().clone();
+            Ok(crate::backend::take_newer(posts, &query.since))
+        }
+
+        // Like the Mastodon API, which can't filter likes at all
+        async fn likes(&self, _query: &Query) -> Result<Vec<Post>> {
+            Ok(self.likes.clone())
+        }
+    }
+
+    fn ids(poll: &Poll) -> Vec<&str> {
+        poll.posts.iter().flatten().map(|p| p.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn likes_are_shown_once() {
+        let account = Account {
+            posts: Mutex::new(vec![test_post("2", minute(2))]),
+            // Liked just now, but posted long before anything else
+            likes: vec![test_post("1", minute(-1000))],
+        };
+        let query = Query {
+            limit: 10,
+            ..Default::default()
+        };
+        let mut poller = Poller::new(&account, &Feed::Me, query, Options::default());
+
+        assert_eq!(ids(&poller.poll().await.unwrap()), vec!["2", "1"]);
+        assert!(ids(&poller.poll().await.unwrap()).is_empty());
+
+        account
+            .posts
+            .lock()
+            .unwrap()
+            .insert(0, test_post("3", minute(3)));
+        assert_eq!(ids(&poller.poll().await.unwrap()), vec!["3"]);
+    }
+
+    fn catching_up(name: &str, saved: &str, catch_up: usize) -> (Replay, Options) {
+        let dir =
+            std::env::temp_dir().join(format!("babble_catch_up_{}_{}", std::process::id(), name));
+        let cursor = Cursor::at(&dir, "home");
+        let place = Place {
+            id: saved.to_string(),
+            time: None,
+        };
+        cursor.save(&place).unwrap();
+        let posts = (0..10)
+            .map(|i| test_post(&i.to_string(), minute(i)))
+            .collect();
+        let options = Options {
+            cursor: Some(cursor),
+            catch_up: Some(catch_up),
+            ..Default::default()
+        };
+        (Replay::from_posts(posts, None), options)
+    }
+
+    fn home() -> Query {
+        Query {
+            limit: 20,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn catching_up_says_when_posts_were_skipped() {
+        let (replay, options) = catching_up("skipped", "4", 3);
+        let mut poller = Poller::new(&replay, &Feed::Home, home(), options);
+        let poll = poller.poll().await.unwrap();
+        assert_eq!(ids(&poll), vec!["9", "8", "7"]);
+        assert!(poll.status.unwrap().contains("older ones were skipped"));
+    }
+
+    #[tokio::test]
+    async fn catching_up_on_exactly_enough_is_quiet() {
+        let (replay, options) = catching_up("exact", "6", 3);
+        let mut poller = Poller::new(&replay, &Feed::Home, home(), options);
+        let poll = poller.poll().await.unwrap();
+        assert_eq!(ids(&poll), vec!["9", "8", "7"]);
+        assert!(poll.status.is_none());
+    }
+}
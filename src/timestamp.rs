@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, FixedOffset, Local, Offset, Utc};
+use chrono_tz::Tz;
+
+// How post times are shown by every format: chrono's full form, a strftime
+// string or relative to now, in the local timezone or a named one.
+
+#[derive(Debug, Clone)]
+pub(crate) struct TimeFormat {
+    style: Style,
+    zone: Option<Tz>,
+}
+
+#[derive(Debug, Clone)]
+enum Style {
+    Full,
+    Relative,
+    Strftime(String),
+}
+
+impl Default for TimeFormat {
+    fn default() -> TimeFormat {
+        TimeFormat {
+            style: Style::Full,
+            zone: None,
+        }
+    }
+}
+
+impl TimeFormat {
+    /// `format` is a strftime string or "relative", and `zone` an IANA
+    /// timezone name like America/Denver or UTC. Either defaults to how
+    /// times have always been shown, in full and in local time.
+    pub(crate) fn new(format: Option<&str>, zone: Option<&str>) -> Result<TimeFormat> {
+        let style = match format {
+            None => Style::Full,
+            Some(format) if format.eq_ignore_ascii_case("relative") => Style::Relative,
+            Some(format) => {
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    return Err(anyhow!("Bad time format {}", format));
+                }
+                Style::Strftime(format.to_string())
+            }
+        };
+        let zone = match zone {
+            None => None,
+            Some(zone) if zone.eq_ignore_ascii_case("local") => None,
+            Some(zone) => Some(
+                zone.parse::<Tz>()
+                    .map_err(|_| anyhow!("Unknown timezone {}", zone))?,
+            ),
+        };
+        Ok(TimeFormat { style, zone })
+    }
+
+    /// The time in the chosen timezone
+    pub(crate) fn local(&self, at: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        let offset = match self.zone {
+            Some(tz) => at.with_timezone(&tz).offset().fix(),
+            None => at.with_timezone(&Local).offset().fix(),
+        };
+        at.with_timezone(&offset)
+    }
+
+    pub(crate) fn format(&self, at: &DateTime<Utc>) -> String {
+        let local = self.local(at);
+        match self.style {
+            Style::Full => local.to_string(),
+            Style::Strftime(ref format) => local.format(format).to_string(),
+            Style::Relative => self.relative(&local),
+        }
+    }
+
+    // Short enough for a narrow stream pane
+    fn relative(&self, at: &DateTime<FixedOffset>) -> String {
+        let now = Utc::now().with_timezone(&at.offset().fix());
+        let ago = now.signed_duration_since(*at);
+        let days = now.num_days_from_ce() - at.num_days_from_ce();
+        if ago.num_seconds() < 45 {
+            "just now".to_string()
+        } else if ago.num_minutes() < 60 {
+            format!("{}m ago", ago.num_minutes().max(1))
+        } else if days == 0 {
+            format!("{}h ago", ago.num_hours())
+        } else if days == 1 {
+            format!("yesterday {}", at.format("%H:%M"))
+        } else if days < 7 {
+            format!("{}d ago", days)
+        } else {
+            at.format("%Y-%m-%d").to_string()
+        }
+    }
+}
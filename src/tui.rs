@@ -0,0 +1,439 @@
+use anyhow::Result;
+use std::io::Write;
+use std::sync::Arc;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, style};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep};
+
+use crate::{
+    backend::{Backend, Feed, Post, Query},
+    format::{self, Formatter},
+    logging, text,
+    timeline::{self, Poll, Poller},
+};
+
+// A full screen view of several feeds side by side, each polled on its own
+// the way --stream polls one. Posts are drawn with the terminal format
+// wrapped to their column, and the selected one can be opened up to show it
+// at full width.
+
+const HELP: &str = "←→ column  ↑↓ post  Enter details  r refresh  q quit";
+// Posts kept per column, the oldest dropped first
+const MAX_POSTS: usize = 500;
+// Columns narrower than this are scrolled sideways instead of squeezed
+const MIN_COLUMN_WIDTH: usize = 30;
+// How often to check whether the terminal has been resized
+const RESIZE_CHECK_MS: u64 = 250;
+// Posts moved by PageUp and PageDown
+const PAGE: usize = 10;
+
+enum Event {
+    Key(Key),
+    Update { column: usize, poll: Result<Poll> },
+}
+
+struct Column {
+    title: String,
+    /// Newest first
+    posts: Vec<Post>,
+    selected: usize,
+    /// The first post drawn
+    top: usize,
+    status: Option<String>,
+}
+
+struct Screen {
+    columns: Vec<Column>,
+    focus: usize,
+    detail: bool,
+    width: usize,
+    height: usize,
+    format: format::Options,
+    // Terminal formatters for a column's width and for the whole screen
+    narrow: Box<dyn Formatter>,
+    wide: Box<dyn Formatter>,
+}
+
+/// Shows each feed in a column until q is pressed. Every feed gets its own
+/// stream options, so each keeps its own place.
+pub(crate) async fn run(
+    backend: Arc<dyn Backend>,
+    feeds: Vec<(Feed, timeline::Options)>,
+    query: Query,
+    format: format::Options,
+) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let refresh = Arc::new(Notify::new());
+    let titles: Vec<String> = feeds.iter().map(|(feed, _)| title(feed)).collect();
+    let tasks: Vec<JoinHandle<()>> = feeds
+        .into_iter()
+        .enumerate()
+        .map(|(column, (feed, options))| {
+            spawn_column(
+                backend.clone(),
+                column,
+                feed,
+                query.clone(),
+                options,
+                tx.clone(),
+                refresh.clone(),
+            )
+        })
+        .collect();
+    listen_for_keys(tx);
+
+    // Dropped after the screen is put back, so held log lines end up below
+    // where the shell left off
+    let _held = logging::hold();
+    let mut out = AlternateScreen::from(std::io::stdout().into_raw_mode()?);
+    write!(out, "{}", cursor::Hide)?;
+    let mut screen = Screen::new(titles, format)?;
+    screen.draw(&mut out)?;
+
+    let mut resize_check = interval(std::time::Duration::from_millis(RESIZE_CHECK_MS));
+    loop {
+        tokio::select! {
+            _ = resize_check.tick() => {
+                if !screen.resize()? {
+                    continue;
+                }
+            }
+            event = rx.recv() => match event {
+                Some(Event::Key(Key::Char('q'))) | Some(Event::Key(Key::Ctrl('c'))) | None => break,
+                Some(Event::Key(Key::Char('r'))) => refresh.notify_waiters(),
+                Some(Event::Key(key)) => screen.key(key),
+                Some(Event::Update { column, poll }) => screen.update(column, poll),
+            }
+        }
+        screen.draw(&mut out)?;
+    }
+
+    for task in tasks {
+        task.abort();
+    }
+    write!(out, "{}", cursor::Show)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn spawn_column(
+    backend: Arc<dyn Backend>,
+    column: usize,
+    feed: Feed,
+    query: Query,
+    options: timeline::Options,
+    tx: mpsc::UnboundedSender<Event>,
+    refresh: Arc<Notify>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut poller = Poller::new(&*backend, &feed, query, options);
+        loop {
+            let poll = poller.poll().await;
+            // Stop polling after an error that retrying won't fix
+            let wait = poll.as_ref().ok().map(|poll| poll.wait);
+            if tx.send(Event::Update { column, poll }).is_err() {
+                return;
+            }
+            match wait {
+                Some(wait) => tokio::select! {
+                    _ = sleep(wait) => (),
+                    _ = refresh.notified() => (),
+                },
+                None => return,
+            }
+        }
+    })
+}
+
+// Reading stdin blocks, so it gets its own thread
+fn listen_for_keys(tx: mpsc::UnboundedSender<Event>) {
+    std::thread::spawn(move || {
+        for key in std::io::stdin().keys() {
+            match key {
+                Ok(key) if tx.send(Event::Key(key)).is_ok() => (),
+                _ => break,
+            }
+        }
+    });
+}
+
+fn title(feed: &Feed) -> String {
+    match feed {
+        Feed::Home => "Home".to_string(),
+        Feed::List(name) => name.clone(),
+        Feed::Me => "Me".to_string(),
+    }
+}
+
+impl Screen {
+    fn new(titles: Vec<String>, format: format::Options) -> Result<Screen> {
+        let columns = titles
+            .into_iter()
+            .map(|title| Column {
+                title,
+                posts: vec![],
+                selected: 0,
+                top: 0,
+                status: Some("Loading…".to_string()),
+            })
+            .collect();
+        let mut screen = Screen {
+            columns,
+            focus: 0,
+            detail: false,
+            width: 0,
+            height: 0,
+            narrow: format::formatter("terminal", &format)?,
+            wide: format::formatter("terminal", &format)?,
+            format,
+        };
+        screen.resize()?;
+        Ok(screen)
+    }
+
+    /// Picks up a new terminal size, returning whether it changed
+    fn resize(&mut self) -> Result<bool> {
+        let (width, height) = termion::terminal_size()?;
+        let (width, height) = (width as usize, height as usize);
+        if (width, height) == (self.width, self.height) {
+            return Ok(false);
+        }
+        self.width = width;
+        self.height = height;
+        // Leave a column for the selection marker
+        self.narrow = self.formatter(self.column_width().saturating_sub(1))?;
+        self.wide = self.formatter(width)?;
+        Ok(true)
+    }
+
+    fn formatter(&self, width: usize) -> Result<Box<dyn Formatter>> {
+        let options = format::Options {
+            width: Some(width.max(1)),
+            hints: None,
+            images: None,
+            ..self.format.clone()
+        };
+        format::formatter("terminal", &options)
+    }
+
+    fn visible_columns(&self) -> usize {
+        ((self.width + 1) / (MIN_COLUMN_WIDTH + 1)).clamp(1, self.columns.len().max(1))
+    }
+
+    // Each visible column is the same width with a one column gap between
+    fn column_width(&self) -> usize {
+        let shown = self.visible_columns();
+        (self.width + 1) / shown - 1
+    }
+
+    // The first visible column, far enough along to show the focused one
+    fn first_column(&self) -> usize {
+        (self.focus + 1).saturating_sub(self.visible_columns())
+    }
+
+    // Rows between the titles and the status line
+    fn body_height(&self) -> usize {
+        self.height.saturating_sub(2)
+    }
+
+    fn update(&mut self, column: usize, poll: Result<Poll>) {
+        let col = &mut self.columns[column];
+        match poll {
+            Ok(poll) => {
+                if let Some(posts) = poll.posts {
+                    let new = posts.len();
+                    // Stay on the same post unless reading from the top
+                    if col.selected > 0 || col.top > 0 {
+                        col.selected += new;
+                        col.top += new;
+                    }
+                    col.posts.splice(0..0, posts);
+                    col.posts.truncate(MAX_POSTS);
+                    col.selected = col.selected.min(col.posts.len().saturating_sub(1));
+                    col.top = col.top.min(col.selected);
+                }
+                col.status = poll.status;
+            }
+            Err(err) => col.status = Some(format!("Stopped: {}", err)),
+        }
+        self.scroll_to_selected(column);
+    }
+
+    fn key(&mut self, key: Key) {
+        if self.detail {
+            if let Key::Esc | Key::Char('\n') | Key::Backspace | Key::Left | Key::Char('h') = key {
+                self.detail = false;
+            }
+            return;
+        }
+        let last_column = self.columns.len().saturating_sub(1);
+        let col = &mut self.columns[self.focus];
+        let last = col.posts.len().saturating_sub(1);
+        match key {
+            Key::Left | Key::Char('h') => self.focus = self.focus.saturating_sub(1),
+            Key::Right | Key::Char('l') => self.focus = (self.focus + 1).min(last_column),
+            Key::Up | Key::Char('k') => col.selected = col.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => col.selected = (col.selected + 1).min(last),
+            Key::PageUp => col.selected = col.selected.saturating_sub(PAGE),
+            Key::PageDown => col.selected = (col.selected + PAGE).min(last),
+            Key::Home | Key::Char('g') => col.selected = 0,
+            Key::End | Key::Char('G') => col.selected = last,
+            Key::Char('\n') if !col.posts.is_empty() => self.detail = true,
+            _ => (),
+        }
+        self.scroll_to_selected(self.focus);
+    }
+
+    fn scroll_to_selected(&mut self, column: usize) {
+        let height = self.body_height();
+        let narrow = &self.narrow;
+        let col = &mut self.columns[column];
+        if col.selected < col.top {
+            col.top = col.selected;
+        }
+        let lines = |post: &Post| post_lines(narrow.as_ref(), post).len() + 1;
+        while col.top < col.selected
+            && col.posts[col.top..=col.selected]
+                .iter()
+                .map(lines)
+                .sum::<usize>()
+                > height
+        {
+            col.top += 1;
+        }
+    }
+
+    fn draw<W: Write>(&self, out: &mut W) -> Result<()> {
+        write!(out, "{}", clear::All)?;
+        if self.detail {
+            self.draw_detail(out)?;
+        } else {
+            let width = self.column_width();
+            let first = self.first_column();
+            for (i, col) in self
+                .columns
+                .iter()
+                .enumerate()
+                .skip(first)
+                .take(self.visible_columns())
+            {
+                let x = (i - first) * (width + 1) + 1;
+                self.draw_column(out, col, i == self.focus, x, width)?;
+            }
+        }
+        // The newest log line, if there's been one, and then the keys
+        let bottom = match logging::newest() {
+            Some(line) => format!("{}  {}", line, HELP),
+            None => HELP.to_string(),
+        };
+        write!(
+            out,
+            "{}{}{}",
+            cursor::Goto(1, self.height as u16),
+            style::Faint,
+            text::fit(&bottom, self.width, style::Reset.as_ref())
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn draw_column<W: Write>(
+        &self,
+        out: &mut W,
+        col: &Column,
+        focused: bool,
+        x: usize,
+        width: usize,
+    ) -> Result<()> {
+        let reset = style::Reset.to_string();
+        let title = match col.status {
+            Some(ref status) => format!(" {} · {}", col.title, status),
+            None => format!(" {}", col.title),
+        };
+        let title_style = if focused {
+            format!("{}{}", style::Bold, style::Invert)
+        } else {
+            style::Bold.to_string()
+        };
+        write!(
+            out,
+            "{}{}{}",
+            cursor::Goto(x as u16, 1),
+            title_style,
+            text::fit(&title, width, &reset)
+        )?;
+
+        let mut y = 2;
+        let bottom = self.height.saturating_sub(1);
+        for (i, post) in col.posts.iter().enumerate().skip(col.top) {
+            let marker = if focused && i == col.selected {
+                "▌"
+            } else {
+                " "
+            };
+            for line in post_lines(self.narrow.as_ref(), post) {
+                if y > bottom {
+                    return Ok(());
+                }
+                write!(
+                    out,
+                    "{}{}{}",
+                    cursor::Goto(x as u16, y as u16),
+                    marker,
+                    text::fit(&line, width.saturating_sub(1), &reset)
+                )?;
+                y += 1;
+            }
+            // A blank line between posts
+            y += 1;
+        }
+        Ok(())
+    }
+
+    fn draw_detail<W: Write>(&self, out: &mut W) -> Result<()> {
+        let col = &self.columns[self.focus];
+        let post = match col.posts.get(col.selected) {
+            Some(post) => post,
+            None => return Ok(()),
+        };
+        let mut lines = post_lines(self.wide.as_ref(), post);
+        let shown = post.reposted.as_deref().unwrap_or(post);
+        let links: Vec<&str> = shown
+            .urls
+            .iter()
+            .map(|link| link.expanded_url.as_deref().unwrap_or(&link.url))
+            .chain(shown.media.iter().map(|media| media.media_url.as_str()))
+            .collect();
+        if !links.is_empty() {
+            lines.push(String::new());
+            lines.extend(links.iter().map(|link| format!("➜ {}", link)));
+        }
+        lines.push(String::new());
+        lines.push(format!("id {}  (Esc to go back)", &post.id));
+
+        let reset = style::Reset.to_string();
+        for (y, line) in lines.iter().take(self.height.saturating_sub(1)).enumerate() {
+            write!(
+                out,
+                "{}{}",
+                cursor::Goto(1, y as u16 + 1),
+                text::fit(line, self.width, &reset)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn post_lines(formatter: &dyn Formatter, post: &Post) -> Vec<String> {
+    formatter
+        .format_post(post)
+        .trim_end()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
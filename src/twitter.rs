@@ -1,20 +1,37 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use egg_mode::{
     list::{List, ListID},
     tweet::{Timeline, Tweet},
 };
-use tokio::time::{sleep, Duration};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
 
-use crate::{ui::UI, MyConfig};
+use crate::{
+    backend::{
+        home_file, locate, Author, Backend, Link, Media, Post, Query, RateLimit, ReplyTo, Tag,
+        TagKind, MAX_PAGES,
+    },
+    MyConfig,
+};
 
 const AUTH_TOKENS_FILE: &'static str = ".twitter_cli_oauth";
 //const CONSUMER_KEY: &'static str = include_str!("consumer_key.in");
 //const CONSUMER_SECRET: &'static str = include_str!("consumer_secret.in");
+// The most the timeline APIs return at once
+const MAX_PAGE_SIZE: usize = 200;
 
 pub(crate) struct Twitter {
     token: egg_mode::Token,
     user_id: u64,
     screen_name: String,
+    // List names are resolved to ids once so streaming doesn't refetch them
+    list_ids: Mutex<HashMap<String, u64>>,
+    // With --record, every tweet fetched so far (newest first) and where to
+    // save them
+    recording: Option<(PathBuf, Mutex<Vec<Tweet>>)>,
+    rate_limit: Mutex<Option<RateLimit>>,
 }
 
 impl Twitter {
@@ -24,105 +41,207 @@ impl Twitter {
             Err(_) => login(&cfg).await?,
         };
 
-        let standard_font = figlet_rs::FIGfont::standand().unwrap();
-        let figure = standard_font.convert(&("@".to_string() + &screen_name));
-        println!("{}", figure.unwrap());
-
         Ok(Twitter {
             token,
             user_id,
             screen_name,
+            list_ids: Mutex::new(HashMap::new()),
+            recording: None,
+            rate_limit: Mutex::new(None),
         })
     }
 
-    pub(crate) async fn me(&self) -> Result<Timeline> {
-        // TODO: include likes
-        // egg_mode::tweet::liked_by<T: Into<UserID>>(acct: T, token: &Token) -> Timeline
-        Ok(egg_mode::tweet::user_timeline(
-            self.user_id,
-            true,
-            true,
-            &self.token,
-        ))
+    pub(crate) fn record_to(self, path: PathBuf) -> Self {
+        Twitter {
+            recording: Some((path, Mutex::new(vec![]))),
+            ..self
+        }
     }
 
-    pub(crate) async fn list(&self, list_name: &str) -> Result<Timeline> {
+    // The whole file is rewritten each time so it's always a valid JSON
+    // array that --replay can read
+    fn record(&self, tweets: &[Tweet]) -> Result<()> {
+        if let Some((ref path, ref recorded)) = self.recording {
+            let mut recorded = recorded.lock().unwrap();
+            recorded.extend(tweets.iter().cloned());
+            recorded.sort_by_key(|t| std::cmp::Reverse(t.id));
+            recorded.dedup_by_key(|t| t.id);
+            let file = std::fs::File::create(path)?;
+            serde_json::to_writer(file, &*recorded)?;
+        }
+        Ok(())
+    }
+
+    async fn list_id(&self, list_name: &str) -> Result<u64> {
+        let key = list_name.to_lowercase();
+        if let Some(id) = self.list_ids.lock().unwrap().get(&key) {
+            return Ok(*id);
+        }
         let lists = egg_mode::list::list(self.user_id, true, &self.token).await?;
         let list: &List = (*lists)
             .iter()
-            .find(|l| l.name.to_lowercase() == list_name.to_lowercase())
+            .find(|l| l.name.to_lowercase() == key)
             .ok_or(anyhow!("List not found"))?;
-        Ok(egg_mode::list::statuses(
-            ListID::from_id(list.id),
-            true,
-            &self.token,
-        ))
+        self.list_ids.lock().unwrap().insert(key, list.id);
+        Ok(list.id)
     }
 
-    pub(crate) async fn home(&self) -> Result<Timeline> {
-        Ok(egg_mode::tweet::home_timeline(&self.token))
+    async fn fetch_timeline(&self, timeline: Timeline, query: &Query) -> Result<Vec<Post>> {
+        let since_id = query.since.as_ref().map(|id| id.parse()).transpose()?;
+        let mut timeline = timeline.with_page_size(query.page_size(MAX_PAGE_SIZE) as i32);
+        let mut posts: Vec<Post> = vec![];
+        // Each call to older picks up below the oldest tweet seen so far
+        for _ in 0..MAX_PAGES {
+            let (next, tweets) = timeline.older(since_id).await?;
+            timeline = next;
+            let status = &tweets.rate_limit_status;
+            *self.rate_limit.lock().unwrap() =
+                RateLimit::new(status.remaining as i64, status.reset as i64);
+            self.record(&tweets)?;
+            posts.extend(tweets.iter().map(Post::from));
+            if !query.wants_more(&posts, tweets.len(), MAX_PAGE_SIZE) {
+                break;
+            }
+        }
+        Ok(query.keep(posts))
     }
+}
 
-    pub(crate) async fn timeline_print(
-        &self,
-        timeline: Timeline,
-        ui: &UI,
-        page_size: i32,
-        markdown: bool,
-    ) -> Result<()> {
-        let tweets = timeline.with_page_size(page_size);
-        let (_tweets, feed) = tweets.start().await?;
-        self.print_feed(&ui, feed.iter().rev(), markdown).await;
-        Ok(())
+#[async_trait]
+impl Backend for Twitter {
+    fn handle(&self) -> &str {
+        &self.screen_name
     }
 
-    pub(crate) async fn timeline_stream(
-        &self,
-        timeline: Timeline,
-        ui: &UI,
-        page_size: i32,
-        markdown: bool,
-    ) -> Result<()> {
-        let mut tweets = timeline.with_page_size(page_size);
-        let tmp = tweets.start().await?;
-        tweets = tmp.0;
-        let mut feed = tmp.1;
-        self.print_feed(&ui, feed.iter().rev(), markdown).await;
-
-        loop {
-            let tmp = tweets.newer(None).await?;
-            // TODO: handle twitter's backoff response properly
-            tweets = tmp.0;
-            feed = tmp.1;
-            // let mut max_id = home.max_id;
-            self.print_feed(&ui, feed.iter().rev(), markdown).await;
-            sleep(Duration::from_millis(120000)).await;
-        }
-        Ok(())
+    fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
     }
 
-    async fn print_feed<'a, I>(&self, ui: &UI, feed: I, markdown: bool)
-    where
-        I: Iterator<Item = &'a Tweet>,
-    {
-        for status in feed {
-            if markdown {
-                ui.print_tweet_markdown(status).await;
-            } else {
-                ui.print_tweet(status).await;
-            }
+    async fn me(&self, query: &Query) -> Result<Vec<Post>> {
+        self.fetch_timeline(
+            egg_mode::tweet::user_timeline(self.user_id, true, true, &self.token),
+            query,
+        )
+        .await
+    }
+
+    async fn likes(&self, query: &Query) -> Result<Vec<Post>> {
+        self.fetch_timeline(egg_mode::tweet::liked_by(self.user_id, &self.token), query)
+            .await
+    }
+
+    async fn list(&self, list_name: &str, query: &Query) -> Result<Vec<Post>> {
+        let list_id = self.list_id(list_name).await?;
+        self.fetch_timeline(
+            egg_mode::list::statuses(ListID::from_id(list_id), true, &self.token),
+            query,
+        )
+        .await
+    }
+
+    async fn home(&self, query: &Query) -> Result<Vec<Post>> {
+        self.fetch_timeline(egg_mode::tweet::home_timeline(&self.token), query)
+            .await
+    }
+}
+
+impl From<&Tweet> for Post {
+    fn from(tweet: &Tweet) -> Post {
+        // Twitter escapes <, > and & in the text, and only Twitter does
+        let text = html_escape::decode_html_entities(&tweet.text).into_owned();
+        Post {
+            id: tweet.id.to_string(),
+            author: tweet
+                .user
+                .as_ref()
+                .map(|u| Author {
+                    handle: u.screen_name.clone(),
+                    name: u.name.clone(),
+                    url: format!("https://twitter.com/{}", u.screen_name),
+                })
+                .unwrap_or_default(),
+            created_at: tweet.created_at,
+            urls: tweet
+                .entities
+                .urls
+                .iter()
+                .map(|u| Link {
+                    url: u.url.clone(),
+                    display_url: u.display_url.clone(),
+                    expanded_url: u.expanded_url.clone(),
+                    range: locate(&text, u.range, &u.url),
+                })
+                .collect(),
+            media: tweet
+                .entities
+                .media
+                .iter()
+                .flatten()
+                .map(|m| Media {
+                    url: m.url.clone(),
+                    display_url: m.display_url.clone(),
+                    media_url: m.media_url_https.clone(),
+                    range: locate(&text, m.range, &m.url),
+                })
+                .collect(),
+            tags: tags(tweet, &text),
+            text,
+            repost_count: tweet.retweet_count.max(0) as u64,
+            like_count: tweet.favorite_count.max(0) as u64,
+            source: tweet.source.as_ref().map(|s| s.name.clone()),
+            place: tweet.place.as_ref().map(|p| p.full_name.clone()),
+            reply_to: tweet.in_reply_to_screen_name.as_ref().map(|name| ReplyTo {
+                handle: name.clone(),
+                // TODO: if in_reply_to_status_id is None then need to return empty url
+                url: format!(
+                    "https://twitter.com/{}/status/{}",
+                    name,
+                    tweet.in_reply_to_status_id.unwrap_or(0)
+                ),
+            }),
+            reposted: tweet
+                .retweeted_status
+                .as_ref()
+                .map(|rt| Box::new(Post::from(&**rt))),
+            quoted: tweet
+                .quoted_status
+                .as_ref()
+                .map(|qt| Box::new(Post::from(&**qt))),
+            activity: None,
         }
     }
 }
 
-fn auth_tokens_file_path() -> std::path::PathBuf {
-    let mut path = std::env::home_dir().unwrap();
-    path.push(AUTH_TOKENS_FILE);
-    path
+// The entity ranges are into the escaped text, so they're checked against
+// the decoded text and looked up again when they've moved
+fn tags(tweet: &Tweet, text: &str) -> Vec<Tag> {
+    let entities = &tweet.entities;
+    let mentions = entities
+        .user_mentions
+        .iter()
+        .map(|m| (TagKind::Mention, m.range, format!("@{}", &m.screen_name)));
+    let hashtags = entities
+        .hashtags
+        .iter()
+        .map(|h| (TagKind::Hashtag, h.range, format!("#{}", &h.text)));
+    let symbols = entities
+        .symbols
+        .iter()
+        .map(|s| (TagKind::Symbol, s.range, format!("${}", &s.text)));
+    mentions
+        .chain(hashtags)
+        .chain(symbols)
+        .filter_map(|(kind, range, needle)| {
+            Some(Tag {
+                kind,
+                range: locate(text, range, &needle)?,
+            })
+        })
+        .collect()
 }
 
 async fn fetch_login(cfg: &MyConfig) -> Result<(egg_mode::auth::Token, u64, String)> {
-    let file = std::fs::File::open(auth_tokens_file_path())?;
+    let file = std::fs::File::open(home_file(AUTH_TOKENS_FILE))?;
     let reader = std::io::BufReader::new(file);
     let access_token: egg_mode::auth::KeyPair = serde_json::from_reader(reader)?;
 
@@ -139,7 +258,7 @@ async fn fetch_login(cfg: &MyConfig) -> Result<(egg_mode::auth::Token, u64, Stri
     // TODO: if tokens don't verify, should probably delete the file
     // println!("We've hit an error using your old tokens: {:?}", err);
     // println!("We'll have to reauthenticate before continuing.");
-    // std::fs::remove_file(auth_tokens_file_path()).unwrap();
+    // std::fs::remove_file(home_file(AUTH_TOKENS_FILE)).unwrap();
     Ok((token, user.id, user.screen_name.to_string()))
 }
 
@@ -154,7 +273,7 @@ async fn save_login(token: &egg_mode::auth::Token) -> Result<()> {
             .create(true)
             .write(true)
             .truncate(true)
-            .open(auth_tokens_file_path())?;
+            .open(home_file(AUTH_TOKENS_FILE))?;
         serde_json::to_writer(file, tok)?;
     }
     Ok(())
@@ -169,15 +288,15 @@ async fn login(cfg: &MyConfig) -> Result<(egg_mode::auth::Token, u64, String)> {
     );
 
     // "oob" is needed for PIN-based auth; see docs for `request_token` for more info
-    println!("Fetching request token");
+    eprintln!("Fetching request token");
     let request_token = egg_mode::auth::request_token(&con_token, "oob")
         .await
         .unwrap();
-    println!("Getting authorization URL");
+    eprintln!("Getting authorization URL");
     let auth_url = egg_mode::auth::authorize_url(&request_token);
 
     // give auth_url to the user, they can sign in to Twitter and accept your app's permissions.
-    println!(
+    eprintln!(
         "Please open {} and grant access, then paste the PIN back here:",
         &auth_url
     );
@@ -200,3 +319,38 @@ async fn login(cfg: &MyConfig) -> Result<(egg_mode::auth::Token, u64, String)> {
 
     Ok((token, user_id, screen_name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_entities_once() {
+        let tweet: Tweet = serde_json::from_value(serde_json::json!({
+            "created_at": "Wed Oct 10 20:19:24 +0000 2018",
+            "entities": {
+                "hashtags": [],
+                "symbols": [],
+                "urls": [],
+                "user_mentions": [{
+                    "id": 2,
+                    "indices": [31, 35],
+                    "name": "Bob",
+                    "screen_name": "bob",
+                }],
+            },
+            "favorite_count": 0,
+            "id": 1,
+            "retweet_count": 0,
+            "source": "<a href=\"https://twitter.com\" rel=\"nofollow\">Twitter Web App</a>",
+            "text": "Fish &amp; chips &lt;3 &amp;lt; @bob",
+            "truncated": false,
+        }))
+        .unwrap();
+
+        let post = Post::from(&tweet);
+        assert_eq!(post.text, "Fish & chips <3 &lt; @bob");
+        let (start, end) = post.tags[0].range;
+        assert_eq!(&post.text[start..end], "@bob");
+    }
+}